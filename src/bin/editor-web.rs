@@ -5,14 +5,35 @@ macro_rules! cloned {
     }};
 }
 
-use deltaq_rs::{cdf_to_svg, DeltaQ, DeltaQComponent, DeltaQContext, EvaluationContext, CDF};
+use deltaq_rs::{
+    cdf_to_svg, summary_to_html, DeltaQ, DeltaQComponent, DeltaQContext, EvaluationContext,
+    Location, Summary, CDF,
+};
 use html::RenderResult;
+use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{HtmlInputElement, RequestInit};
 use yew::{platform, prelude::*, suspense::use_future_with};
 
+#[derive(serde::Deserialize)]
+struct EvalResponse {
+    cdf: CDF,
+    summary: Summary,
+    optimistic: Option<CDF>,
+}
+
+/// The part of the evaluation report of a name needed for marking broken expressions and those
+/// relying on assumed outcomes.
+#[derive(serde::Deserialize)]
+struct NameReport {
+    error: Option<String>,
+    location: Option<Location>,
+    #[serde(default)]
+    assumed: BTreeSet<String>,
+}
+
 #[hook]
 fn use_json<D: PartialEq + 'static, T: for<'a> serde::Deserialize<'a>>(
     dep: D,
@@ -42,6 +63,8 @@ fn use_json<D: PartialEq + 'static, T: for<'a> serde::Deserialize<'a>>(
     })
 }
 
+/// Send the value to the given URL, returning the response body as error if the request was
+/// rejected.
 async fn put_json<T: serde::Serialize>(url: &str, value: T) -> Result<JsValue, JsValue> {
     let window = web_sys::window().unwrap();
     let value = serde_json::to_string(&value).unwrap();
@@ -53,13 +76,15 @@ async fn put_json<T: serde::Serialize>(url: &str, value: T) -> Result<JsValue, J
         init.set_headers(&headers);
     }
     init.set_body(&value.into());
-    JsFuture::from(
-        JsFuture::from(window.fetch_with_str_and_init(url, &init))
-            .await?
-            .dyn_into::<web_sys::Response>()?
-            .text()?,
-    )
-    .await
+    let response = JsFuture::from(window.fetch_with_str_and_init(url, &init))
+        .await?
+        .dyn_into::<web_sys::Response>()?;
+    let body = JsFuture::from(response.text()?).await?;
+    if response.ok() {
+        Ok(body)
+    } else {
+        Err(body)
+    }
 }
 
 async fn delete_path(url: &str) -> Result<JsValue, JsValue> {
@@ -91,8 +116,10 @@ fn app_main() -> HtmlResult {
 
     // epoch counter to trigger recomputation when the context changes
     let epoch = use_state(|| 0);
+    // the error from the last change, e.g. a definition that would create a cycle
+    let put_error = use_state(|| None::<String>);
 
-    let cdf = use_json::<_, CDF>(
+    let cdf = use_json::<_, EvalResponse>(
         (selected.clone(), epoch.clone()),
         cloned!(location; move |selected| {
             (*selected)
@@ -103,13 +130,30 @@ fn app_main() -> HtmlResult {
         }),
     )?;
 
+    // evaluate all names to mark the broken ones and those not fully elaborated
+    let reports = use_json::<_, BTreeMap<String, NameReport>>(
+        epoch.clone(),
+        cloned!(location; move |_| Ok(format!("{location}delta_q?evaluate=all"))),
+    )?
+    .unwrap_or_default();
+
     let ctx = use_reducer(move || ctx);
-    let on_change = cloned!(ctx, epoch, location;
+    let on_change = cloned!(ctx, epoch, location, put_error;
         Callback::from(move |(name, dq): (String, Option<DeltaQ>)| {
+            // reject a definition that would create a cycle right away, like the server does
+            if let Some(dq) = &dq {
+                if let Err(e) = (*ctx).clone().put(name.clone(), dq.clone()) {
+                    put_error.set(Some(e.to_string()));
+                    return;
+                }
+            }
             ctx.dispatch((name.clone(), dq.clone()));
-            platform::spawn_local(cloned!(epoch, location; async move {
+            platform::spawn_local(cloned!(epoch, location, put_error; async move {
                 if let Some(dq) = dq {
-                    put_json(&format!("{location}delta_q/{name}"), dq).await.unwrap();
+                    match put_json(&format!("{location}delta_q/{name}"), dq).await {
+                        Ok(_) => put_error.set(None),
+                        Err(e) => put_error.set(Some(e.as_string().unwrap_or_else(|| format!("{e:?}")))),
+                    }
                 } else {
                     delete_path(&format!("{location}delta_q/{name}")).await.unwrap();
                 }
@@ -124,10 +168,22 @@ fn app_main() -> HtmlResult {
         .map(|(k, v)| {
             let name = k.clone();
             let onclick = onclick.clone();
+            let report = reports.get(k);
+            let error = report.and_then(|r| r.error.clone());
+            let assumed = report.map(|r| &r.assumed).filter(|a| !a.is_empty());
+            let class = classes!(
+                "expression",
+                error.is_some().then_some("broken"),
+                assumed.is_some().then_some("provisional")
+            );
+            let title = error.or_else(|| {
+                let assumed = assumed?.iter().cloned().collect::<Vec<_>>();
+                Some(format!("assumed: {}", assumed.join(", ")))
+            });
             let mut h = html! {
                 <li>
                     <button onclick={cloned!(name, on_change; move |_| on_change.emit((name.clone(), None)))}>{ "delete "}</button>
-                    <span class={classes!("expression")} style="margin-left: 8px;" onclick={onclick.reform(move |_| name.clone())}>{ format!("{k}: {v}") }</span>
+                    <span {class} {title} style="margin-left: 8px;" onclick={onclick.reform(move |_| name.clone())}>{ format!("{k}: {v}") }</span>
                 </li>
             };
             if selected.as_ref() == Some(k) {
@@ -143,10 +199,28 @@ fn app_main() -> HtmlResult {
     }
 
     let dq = selected.as_ref().and_then(|name| ctx.get(name));
+    // the path to the failing sub-expression, if the failure lies within the selected definition
+    let error = selected.as_ref().and_then(|name| {
+        let location = reports.get(name)?.location.as_ref()?;
+        (location.name.as_ref() == Some(name)).then(|| location.path.clone())
+    });
     web_sys::console::log_1(&JsValue::from_str(&format!("{dq:?}")));
 
     let cdf = match cdf {
-        Ok(cdf) => cdf_to_svg(&cdf),
+        Ok(EvalResponse {
+            cdf,
+            summary,
+            optimistic,
+        }) => html! {
+            <>
+                { cdf_to_svg(&cdf) }
+                { summary_to_html(&summary) }
+                if let Some(optimistic) = optimistic {
+                    <p>{ "optimistic bound of the assumed outcomes:" }</p>
+                    { cdf_to_svg(&optimistic) }
+                }
+            </>
+        },
         Err(e) => html! { <p>{ "no CDF result: " }{ e }</p> },
     };
 
@@ -162,11 +236,14 @@ fn app_main() -> HtmlResult {
         { list_items }
         </ul>
         <AddExpression on_change={add_on_change} />
+        if let Some(e) = &*put_error {
+            <p class={classes!("error")}>{ "rejected: " }{ e }</p>
+        }
         if let (Some(name), Some(dq)) = (selected.as_ref(), dq) {
             <p>{ "selected: " } { name }</p>
             <div style="background-color: #f0f0f0; padding: 4px; margin: 4px; display: flex; flex-direction: row;">
                 <ContextProvider<DeltaQContext> context={DeltaQContext::new(&ctx, &name)}>
-                    <DeltaQComponent delta_q={dq.clone()} {on_change} />
+                    <DeltaQComponent delta_q={dq.clone()} {on_change} {error} />
                 </ContextProvider<DeltaQContext>>
             </div>
             { cdf }
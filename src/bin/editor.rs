@@ -1,8 +1,9 @@
 use actix_web::{delete, put};
 use actix_web::{get, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
-use deltaq_rs::{DeltaQ, EvaluationContext, CDF};
+use deltaq_rs::{DeltaQ, EvaluationContext, Location, Precision, Requirement, Summary, CDF};
 use include_dir::{include_dir, Dir};
 use parking_lot::Mutex;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io;
 use tracing_subscriber::EnvFilter;
 
@@ -48,22 +49,110 @@ async fn assets(req: HttpRequest) -> impl Responder {
     )
 }
 
+#[derive(serde::Deserialize)]
+struct ListParams {
+    evaluate: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct ReportResponse {
+    #[serde(flatten)]
+    result: Option<EvalResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The sub-expression where the error occurred, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<Location>,
+    missing: BTreeSet<String>,
+    black_boxes: BTreeSet<String>,
+    assumed: BTreeSet<String>,
+}
+
+/// Get all expressions, or with `?evaluate=all` the outcome of evaluating each of them.
 #[get("/delta_q")]
-async fn delta_q(data: web::Data<Data>) -> impl Responder {
+async fn delta_q(data: web::Data<Data>, params: web::Query<ListParams>) -> impl Responder {
     tracing::info!("GET /delta_q");
-    HttpResponse::Ok()
-        .insert_header(("Cache-Control", "no-store"))
-        .json(&*data.ctx.lock())
+    let mut ctx = data.ctx.lock();
+    match params.evaluate.as_deref() {
+        None => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(&*ctx),
+        Some("all") => {
+            let reports = ctx
+                .eval_all()
+                .into_iter()
+                .map(|(name, report)| {
+                    let (result, error, location) = match report.result {
+                        Ok(cdf) => (
+                            Some(EvalResponse {
+                                summary: cdf.summary(),
+                                cdf,
+                                optimistic: None,
+                            }),
+                            None,
+                            None,
+                        ),
+                        Err(e) => (None, Some(e.to_string()), e.location().cloned()),
+                    };
+                    let response = ReportResponse {
+                        result,
+                        error,
+                        location,
+                        missing: report.missing,
+                        black_boxes: report.black_boxes,
+                        assumed: report.assumed,
+                    };
+                    (name, response)
+                })
+                .collect::<BTreeMap<_, _>>();
+            HttpResponse::Ok()
+                .insert_header(("Cache-Control", "no-store"))
+                .json(reports)
+        }
+        Some(other) => HttpResponse::BadRequest()
+            .body(format!("unknown evaluation `{}`, expected `all`", other)),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct EvalParams {
+    precision: Option<Precision>,
+}
+
+#[derive(serde::Serialize)]
+struct EvalResponse {
+    cdf: CDF,
+    summary: Summary,
+    /// The outcome for the optimistic bounds of assumed envelopes, if it differs from `cdf`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    optimistic: Option<CDF>,
 }
 
 #[get("/delta_q/{name}")]
-async fn get_delta_q(data: web::Data<Data>, name: web::Path<String>) -> impl Responder {
+async fn get_delta_q(
+    data: web::Data<Data>,
+    name: web::Path<String>,
+    params: web::Query<EvalParams>,
+) -> impl Responder {
     tracing::info!("GET /delta_q/{}", name);
     let mut ctx = data.ctx.lock();
-    match ctx.eval(&name) {
-        Ok(dq) => HttpResponse::Ok()
+    let result = match params.precision {
+        // evaluate in a copy to keep the cached results in the default precision
+        Some(precision) if precision != ctx.precision() => {
+            let mut ctx = ctx.clone();
+            ctx.set_precision(precision);
+            ctx.eval_envelope(&name)
+        }
+        _ => ctx.eval_envelope(&name),
+    };
+    match result {
+        Ok((cdf, upper)) => HttpResponse::Ok()
             .insert_header(("Cache-Control", "no-store"))
-            .json(dq),
+            .json(EvalResponse {
+                summary: cdf.summary(),
+                optimistic: (upper != cdf).then_some(upper),
+                cdf,
+            }),
         Err(e) => HttpResponse::NotFound()
             .insert_header(("Cache-Control", "no-store"))
             .body(e.to_string()),
@@ -78,8 +167,10 @@ async fn put_delta_q(
 ) -> impl Responder {
     tracing::info!("PUT /delta_q/{}", name);
     let mut ctx = data.ctx.lock();
-    ctx.put(name.into_inner(), dq.into_inner());
-    HttpResponse::Ok().finish()
+    match ctx.put(name.into_inner(), dq.into_inner()) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => HttpResponse::BadRequest().body(e.to_string()),
+    }
 }
 
 #[delete("/delta_q/{name}")]
@@ -93,6 +184,60 @@ async fn delete_delta_q(data: web::Data<Data>, name: web::Path<String>) -> impl
     }
 }
 
+#[get("/delta_q/{name}/requirement")]
+async fn get_requirement(data: web::Data<Data>, name: web::Path<String>) -> impl Responder {
+    tracing::info!("GET /delta_q/{}/requirement", name);
+    let ctx = data.ctx.lock();
+    match ctx.requirement(&name) {
+        Some(req) => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(req),
+        None => HttpResponse::NotFound()
+            .insert_header(("Cache-Control", "no-store"))
+            .body(format!("No requirement for {}", name)),
+    }
+}
+
+#[put("/delta_q/{name}/requirement")]
+async fn put_requirement(
+    data: web::Data<Data>,
+    name: web::Path<String>,
+    req: web::Json<Requirement>,
+) -> impl Responder {
+    tracing::info!("PUT /delta_q/{}/requirement", name);
+    let mut ctx = data.ctx.lock();
+    ctx.set_requirement(name.into_inner(), req.into_inner());
+    HttpResponse::Ok().finish()
+}
+
+#[delete("/delta_q/{name}/requirement")]
+async fn delete_requirement(data: web::Data<Data>, name: web::Path<String>) -> impl Responder {
+    tracing::info!("DELETE /delta_q/{}/requirement", name);
+    let mut ctx = data.ctx.lock();
+    if ctx.remove_requirement(&name).is_some() {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+#[get("/delta_q/{name}/slack")]
+async fn get_slack(data: web::Data<Data>, name: web::Path<String>) -> impl Responder {
+    tracing::info!("GET /delta_q/{}/slack", name);
+    let mut ctx = data.ctx.lock();
+    match ctx.slack(&name) {
+        Ok(Some(slack)) => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(slack),
+        Ok(None) => HttpResponse::NotFound()
+            .insert_header(("Cache-Control", "no-store"))
+            .body(format!("No requirement for {}", name)),
+        Err(e) => HttpResponse::NotFound()
+            .insert_header(("Cache-Control", "no-store"))
+            .body(e.to_string()),
+    }
+}
+
 #[actix_web::main]
 async fn main() -> io::Result<()> {
     tracing_subscriber::fmt()
@@ -105,11 +250,12 @@ async fn main() -> io::Result<()> {
 
     // add two delta_q to the context
     let mut ctx = data.ctx.lock();
-    ctx.put("black".to_owned(), DeltaQ::BlackBox);
+    ctx.put("black".to_owned(), DeltaQ::BlackBox).unwrap();
     ctx.put(
         "cdf".to_owned(),
         DeltaQ::cdf(CDF::step(&[(0.1, 0.33), (0.2, 0.66), (0.4, 1.0)], 0.01, 300).unwrap()),
-    );
+    )
+    .unwrap();
     ctx.put(
         "out".to_owned(),
         DeltaQ::seq(
@@ -124,7 +270,8 @@ async fn main() -> io::Result<()> {
                 3.0,
             ),
         ),
-    );
+    )
+    .unwrap();
     drop(ctx);
 
     let server = HttpServer::new(move || {
@@ -135,6 +282,10 @@ async fn main() -> io::Result<()> {
             .service(get_delta_q)
             .service(put_delta_q)
             .service(delete_delta_q)
+            .service(get_requirement)
+            .service(put_requirement)
+            .service(delete_requirement)
+            .service(get_slack)
             .route("/{f:.*}", web::get().to(assets))
     })
     .workers(1);
@@ -1,12 +1,21 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// The factor by which the direct convolution must be more expensive than the FFT (as estimated
+/// by `N log N`) before the latter is used.
+const FFT_THRESHOLD: usize = 8;
 
 #[derive(Debug, PartialEq)]
 pub enum CDFError {
     InvalidDataRange,
     NonMonotonicData,
     BinSizeMismatch,
-    LengthMismatch,
     InvalidFraction,
+    InvalidBinSize,
+    InvalidParameter(String),
 }
 
 impl std::fmt::Display for CDFError {
@@ -20,45 +29,699 @@ impl std::fmt::Display for CDFError {
                 "Data vector must contain monotonically increasing values"
             ),
             CDFError::BinSizeMismatch => write!(f, "CDFs must have the same bin size"),
-            CDFError::LengthMismatch => write!(f, "CDFs must have the same length"),
             CDFError::InvalidFraction => write!(f, "Fraction must be between 0 and 1"),
+            CDFError::InvalidBinSize => write!(f, "Bin size must be positive and finite"),
+            CDFError::InvalidParameter(msg) => write!(f, "Invalid parameter: {}", msg),
         }
     }
 }
 
 impl std::error::Error for CDFError {}
 
+/// The rounding mode to use when resampling a CDF onto a different grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Move probability mass to the end of the new bin it falls into, so that the resampled
+    /// CDF is never above the original one.
+    Conservative,
+    /// Move probability mass to the start of the new bin it falls into, so that the resampled
+    /// CDF is never below the original one.
+    Optimistic,
+}
+
+/// The numeric representation used for storing the values of a CDF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    /// 16-bit fixed point values, which is compact but accumulates rounding errors in deep models.
+    #[default]
+    Fixed16,
+    /// 64-bit floating point values.
+    Float64,
+}
+
+/// Statistical summary of a CDF, see [`CDF::summary`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Summary {
+    /// The mean time of the outcome, given that it occurs within the horizon.
+    pub mean: Option<f32>,
+    /// The variance of the time of the outcome, given that it occurs within the horizon.
+    pub variance: Option<f32>,
+    pub p50: Option<f32>,
+    pub p95: Option<f32>,
+    pub p99: Option<f32>,
+    pub p99_9: Option<f32>,
+    /// The probability of the outcome occurring eventually, including beyond the horizon.
+    pub completion: f32,
+    /// The part of the completion probability that lies beyond the horizon.
+    pub truncated: f32,
+}
+
 /// A Cumulative Distribution Function (CDF) is a representation of a probability
 /// distribution that can be manipulated in various ways.
-#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+///
+/// The CDF is stored as a vector of bins, and its horizon (i.e. its width) is given by the
+/// number of bins times the bin size. Beyond the horizon the CDF is taken to remain constant
+/// at its last value, which allows CDFs of different widths to be combined.
+///
+/// Probability mass that would complete only after the horizon (e.g. because a convolution
+/// result was cut off) is not simply lost but recorded separately, see [`CDF::truncated_mass`].
+/// This allows distinguishing failure from running off the end of the CDF.
+///
+/// The values are stored with the [`Precision`] chosen via [`CDF::with_precision`]; combining
+/// CDFs of different precisions yields a result in the higher precision.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct CDF {
-    data: Vec<u16>,
+    #[serde(flatten)]
+    values: Values,
     bin_size: f32,
 }
 
+/// Dispatch an expression over the samples of a CDF, regardless of their precision.
+macro_rules! with_samples {
+    ($values:expr, $s:ident => $e:expr) => {
+        match $values {
+            Values::Fixed($s) => $e,
+            Values::Float($s) => $e,
+        }
+    };
+}
+
+/// Transform the samples of a CDF, keeping their precision.
+macro_rules! map_samples {
+    ($values:expr, $s:ident => $e:expr) => {
+        match $values {
+            Values::Fixed($s) => Values::Fixed($e),
+            Values::Float($s) => Values::Float($e),
+        }
+    };
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum Values {
+    Fixed(Samples<u16>),
+    Float(Samples<f64>),
+}
+
+impl Values {
+    fn precision(&self) -> Precision {
+        match self {
+            Values::Fixed(_) => Precision::Fixed16,
+            Values::Float(_) => Precision::Float64,
+        }
+    }
+
+    fn len(&self) -> usize {
+        with_samples!(self, s => s.data.len())
+    }
+
+    /// Get the value at the given bin index for display purposes.
+    fn value_f32(&self, index: usize) -> f32 {
+        with_samples!(self, s => s.value_at(index).to_f32())
+    }
+
+    /// Get the value at the given bin index as a probability.
+    fn value_f64(&self, index: usize) -> f64 {
+        with_samples!(self, s => s.value_at(index).to_f64())
+    }
+
+    /// The resolution of the stored values, used as tolerance when comparing to probabilities.
+    fn quantum(&self) -> f64 {
+        match self {
+            Values::Fixed(_) => 1.0 / 65535.0,
+            Values::Float(_) => 1e-12,
+        }
+    }
+
+    /// Get the first bin index at which the value reaches the given probability.
+    ///
+    /// Probabilities are given at `f32` resolution, so values within that distance count as
+    /// reaching them.
+    fn quantile_index(&self, probability: f64) -> Option<usize> {
+        let probability = probability - self.quantum().max(f32::EPSILON as f64);
+        let index = with_samples!(self, s => s.data.partition_point(|x| x.to_f64() < probability));
+        (index < self.len()).then_some(index)
+    }
+
+    fn to_float(&self) -> Cow<'_, Samples<f64>> {
+        match self {
+            Values::Fixed(s) => Cow::Owned(Samples {
+                data: s.data.iter().map(|&x| x.to_f64()).collect(),
+                truncated: s.truncated.to_f64(),
+            }),
+            Values::Float(s) => Cow::Borrowed(s),
+        }
+    }
+
+    fn to_fixed(&self) -> Cow<'_, Samples<u16>> {
+        match self {
+            Values::Fixed(s) => Cow::Borrowed(s),
+            Values::Float(s) => Cow::Owned(Samples {
+                data: s.data.iter().map(|&x| u16::from_f64(x)).collect(),
+                truncated: u16::from_f64(s.truncated),
+            }),
+        }
+    }
+}
+
+/// The CDF values in a particular representation, together with the mass beyond the horizon.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct Samples<T> {
+    data: Vec<T>,
+    #[serde(default)]
+    truncated: T,
+}
+
+/// A numeric type that can represent probabilities.
+///
+/// All CDF operations are implemented generically over this trait; the implementation for `u16`
+/// is a fixed point representation of the interval [0, 1].
+trait Value: Copy + PartialOrd + Default {
+    /// The raw representation of probability 1.
+    const ONE: f64;
+    /// Multiply two probabilities.
+    fn mul(self, other: Self) -> Self;
+    /// Compute the probability of either of two independent events occurring.
+    fn or(self, other: Self) -> Self;
+    fn saturating_add(self, other: Self) -> Self;
+    fn saturating_sub(self, other: Self) -> Self;
+    fn from_fraction(fraction: f32) -> Self;
+    fn to_f32(self) -> f32;
+    fn to_f64(self) -> f64;
+    fn from_f64(x: f64) -> Self;
+    /// Get the raw representation, i.e. the probability scaled by [`Value::ONE`].
+    fn raw(self) -> f64;
+    /// Convert from the raw representation, rounding and clamping as necessary.
+    fn from_raw(raw: f64) -> Self;
+
+    /// The number by which [`Value::mul`] divides the product of two raw values.
+    const PRODUCT_SCALE: f64;
+    /// The amount by which [`Value::mul`] raises a product by rounding on average, in raw units.
+    const PRODUCT_BIAS: f64;
+    /// Get the group of densities whose products with a given value are rounded alike, see
+    /// [`Value::product_rounding`].
+    fn density_group(self) -> usize;
+    /// Determine how [`Value::mul`] rounds the products of this value with the densities of the
+    /// given group.
+    ///
+    /// The result must be non-decreasing in this value.
+    fn product_rounding(self, group: usize) -> ProductRounding;
+}
+
+/// How [`Value::mul`] rounds a product, ordered by the value multiplied with a density.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ProductRounding {
+    /// The product is zero.
+    Zero,
+    /// The product is rounded up to one raw unit.
+    Unit,
+    /// The product is rounded in a way that is approximated by [`Value::PRODUCT_BIAS`].
+    Approximate,
+    /// The product is rounded up to the density it is multiplied with.
+    Density,
+}
+
+impl Value for u16 {
+    const ONE: f64 = 65535.0;
+
+    fn mul(self, other: Self) -> Self {
+        mul(self, other)
+    }
+
+    fn or(self, other: Self) -> Self {
+        u16::try_from(
+            (self as u32 + other as u32)
+                .checked_sub(mul(self, other) as u32)
+                .expect("subtraction underflow during for_some"),
+        )
+        .expect("overflow during for_some")
+    }
+
+    fn saturating_add(self, other: Self) -> Self {
+        u16::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other: Self) -> Self {
+        u16::saturating_sub(self, other)
+    }
+
+    fn from_fraction(fraction: f32) -> Self {
+        to_int(fraction)
+    }
+
+    fn to_f32(self) -> f32 {
+        (self as f32 / 65535.0).min(1.0)
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64 / 65535.0
+    }
+
+    fn from_f64(x: f64) -> Self {
+        Self::from_raw(x * 65535.0)
+    }
+
+    fn raw(self) -> f64 {
+        self as f64
+    }
+
+    fn from_raw(raw: f64) -> Self {
+        raw.round().clamp(0.0, 65535.0) as u16
+    }
+
+    const PRODUCT_SCALE: f64 = 65536.0;
+    const PRODUCT_BIAS: f64 = 0.5;
+
+    fn density_group(self) -> usize {
+        // densities of the same bit length, i.e. below 2^group
+        (u16::BITS - self.leading_zeros()) as usize
+    }
+
+    fn product_rounding(self, group: usize) -> ProductRounding {
+        // `mul` rounds up after dividing by 2^16, which is exact if the product or its distance
+        // to the density is below 2^16 for all densities below 2^group
+        let bound = 1u64 << group;
+        if self == 0 {
+            ProductRounding::Zero
+        } else if (65536 - self as u64) * bound <= 65536 {
+            ProductRounding::Density
+        } else if self as u64 * bound <= 65536 {
+            ProductRounding::Unit
+        } else {
+            ProductRounding::Approximate
+        }
+    }
+}
+
+impl Value for f64 {
+    const ONE: f64 = 1.0;
+
+    fn mul(self, other: Self) -> Self {
+        self * other
+    }
+
+    fn or(self, other: Self) -> Self {
+        (self + other - self * other).clamp(0.0, 1.0)
+    }
+
+    fn saturating_add(self, other: Self) -> Self {
+        (self + other).min(1.0)
+    }
+
+    fn saturating_sub(self, other: Self) -> Self {
+        (self - other).max(0.0)
+    }
+
+    fn from_fraction(fraction: f32) -> Self {
+        fraction as f64
+    }
+
+    fn to_f32(self) -> f32 {
+        (self as f32).min(1.0)
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(x: f64) -> Self {
+        x.clamp(0.0, 1.0)
+    }
+
+    fn raw(self) -> f64 {
+        self
+    }
+
+    fn from_raw(raw: f64) -> Self {
+        raw.clamp(0.0, 1.0)
+    }
+
+    const PRODUCT_SCALE: f64 = 1.0;
+    const PRODUCT_BIAS: f64 = 0.0;
+
+    fn density_group(self) -> usize {
+        0
+    }
+
+    fn product_rounding(self, _group: usize) -> ProductRounding {
+        if self == 0.0 {
+            ProductRounding::Zero
+        } else {
+            ProductRounding::Approximate
+        }
+    }
+}
+
+impl<T: Value> Samples<T> {
+    /// Get the value of the CDF at the given bin index, extending the last value beyond the
+    /// horizon.
+    fn value_at(&self, index: usize) -> T {
+        self.data
+            .get(index)
+            .or(self.data.last())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The probability of eventually completing, including the mass beyond the horizon.
+    fn completion(&self) -> T {
+        self.value_at(usize::MAX).saturating_add(self.truncated)
+    }
+
+    /// Find the bin in which the outcome occurs for the given uniformly distributed number
+    /// from [0, 1).
+    ///
+    /// Returns the number of bins if the outcome occurs beyond the horizon, or `None` if it
+    /// fails.
+    fn draw(&self, uniform: f64) -> Option<usize> {
+        let index = self.data.partition_point(|x| x.to_f64() <= uniform);
+        if index < self.data.len() || uniform < self.completion().to_f64() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Combine two CDFs bin by bin, extending the shorter one up to the horizon of the longer one.
+    ///
+    /// The truncated mass of the result is obtained by applying the same function to the
+    /// eventual completion probabilities of both CDFs, which is where the result would end up
+    /// if it had an infinite horizon.
+    fn zip_with(&self, other: &Self, f: impl Fn(T, T) -> T) -> Self {
+        let len = self.data.len().max(other.data.len());
+        let data: Vec<T> = (0..len)
+            .map(|i| f(self.value_at(i), other.value_at(i)))
+            .collect();
+        let completion = f(self.completion(), other.completion());
+        let truncated = completion.saturating_sub(data.last().copied().unwrap_or_default());
+        Self { data, truncated }
+    }
+
+    fn choice(&self, fraction: f32, other: &Self) -> Self {
+        let my_fraction = T::from_fraction(fraction);
+        let fraction = T::from_raw(T::ONE - my_fraction.raw());
+        self.zip_with(other, |x, y| {
+            x.mul(my_fraction).saturating_add(y.mul(fraction))
+        })
+    }
+
+    /// Mix the given CDFs, each taken with the given probability; the probabilities sum to one.
+    fn mixture(branches: &[(f64, &Self)]) -> Self {
+        let len = branches
+            .iter()
+            .map(|(_, s)| s.data.len())
+            .max()
+            .unwrap_or(0);
+        let mix = |value: &dyn Fn(&Self) -> T| {
+            T::from_raw(branches.iter().map(|(p, s)| p * value(s).raw()).sum())
+        };
+        let data: Vec<T> = (0..len).map(|i| mix(&|s| s.value_at(i))).collect();
+        let completion = mix(&|s| s.completion());
+        let truncated = completion.saturating_sub(data.last().copied().unwrap_or_default());
+        Self { data, truncated }
+    }
+
+    /// The CDF of the time at which at least `k` of the given independent outcomes have
+    /// occurred, i.e. of their `k`-th order statistic.
+    fn k_of_n(k: usize, outcomes: &[&Self]) -> Self {
+        let len = outcomes.iter().map(|s| s.data.len()).max().unwrap_or(0);
+        let at_least_k = |value: &dyn Fn(&Self) -> T| {
+            // the probability of exactly j outcomes having occurred so far, where the last entry
+            // collects all cases of at least k
+            let mut occurred = vec![0.0; k + 1];
+            occurred[0] = 1.0;
+            for s in outcomes {
+                let p = value(s).to_f64();
+                occurred[k] += occurred[k - 1] * p;
+                for j in (0..k).rev() {
+                    let from_below = if j > 0 { occurred[j - 1] * p } else { 0.0 };
+                    occurred[j] = occurred[j] * (1.0 - p) + from_below;
+                }
+            }
+            T::from_f64(occurred[k])
+        };
+        let data: Vec<T> = (0..len).map(|i| at_least_k(&|s| s.value_at(i))).collect();
+        let completion = at_least_k(&|s| s.completion());
+        let truncated = completion.saturating_sub(data.last().copied().unwrap_or_default());
+        Self { data, truncated }
+    }
+
+    /// Give up on the outcome after the bin with the given index, turning all later mass into
+    /// failure, and continue with the fallback (if any) from that bin on for the part that has
+    /// not occurred by then, limited to `max_len` bins.
+    fn timeout(&self, index: usize, fallback: Option<&Self>, max_len: usize) -> Self {
+        let in_time = self.value_at(index);
+        let Some(fallback) = fallback else {
+            return Self {
+                data: self.data[..self.data.len().min(index + 1)].to_vec(),
+                truncated: T::default(),
+            };
+        };
+        let timed_out = T::from_raw(T::ONE - in_time.raw());
+        // a deadline beyond the limit leaves all mass of the fallback beyond the horizon
+        let len = index
+            .saturating_add(fallback.data.len().max(1))
+            .min(max_len.max(self.data.len()));
+        let data: Vec<T> = (0..len)
+            .map(|i| match i.checked_sub(index) {
+                Some(j) => in_time.saturating_add(fallback.value_at(j).mul(timed_out)),
+                None => self.value_at(i),
+            })
+            .collect();
+        let completion = in_time.saturating_add(fallback.completion().mul(timed_out));
+        let truncated = completion.saturating_sub(data.last().copied().unwrap_or_default());
+        Self { data, truncated }
+    }
+
+    fn for_all(&self, other: &Self) -> Self {
+        self.zip_with(other, T::mul)
+    }
+
+    fn for_some(&self, other: &Self) -> Self {
+        self.zip_with(other, T::or)
+    }
+
+    /// Resample onto `len` bins, taking the value of each new bin from the old bin index
+    /// computed by `index`.
+    fn resample(&self, len: usize, index: impl Fn(usize) -> usize) -> Self {
+        Self {
+            data: (0..len).map(|j| self.value_at(index(j))).collect(),
+            truncated: self.truncated,
+        }
+    }
+
+    /// Get the probability mass within each bin, i.e. the differences between adjacent values.
+    fn density(&self) -> Vec<T> {
+        let mut last = T::default();
+        self.data
+            .iter()
+            .map(|&x| {
+                let d = x.saturating_sub(last);
+                last = x;
+                d
+            })
+            .collect()
+    }
+
+    /// Convolve two CDFs, limiting the result to `len` bins and recording the mass cut off by
+    /// this limit as truncated.
+    fn convolve(&self, other: &Self, len: usize) -> Self {
+        let density = other.density();
+        let data = if use_fft(&density, len) {
+            self.convolve_fft(&density, len)
+        } else {
+            self.convolve_direct(&density, len)
+        };
+
+        let completion = self.completion();
+        // mass of the other CDF beyond its horizon can only complete beyond our horizon
+        let mut truncated = other.truncated.mul(completion).raw();
+        for (j, &other) in density.iter().enumerate() {
+            if other == T::default() {
+                continue;
+            }
+            let beyond = if j < len {
+                completion.saturating_sub(self.value_at(len - 1 - j))
+            } else {
+                completion
+            };
+            truncated += beyond.mul(other).raw();
+        }
+
+        Self {
+            data,
+            truncated: T::from_raw(truncated),
+        }
+    }
+
+    /// Convolve this CDF with the given density by summing up all products, limited to `len` bins.
+    fn convolve_direct(&self, density: &[T], len: usize) -> Vec<T> {
+        // the raw values of the fixed point representation are summed up exactly
+        let mut convolved_data: Vec<f64> = vec![0.0; len];
+        for (j, &other) in density.iter().enumerate().take(len) {
+            if other == T::default() {
+                continue;
+            }
+            for (i, value) in convolved_data[j..].iter_mut().enumerate() {
+                *value += self.value_at(i).mul(other).raw();
+            }
+        }
+        convolved_data.into_iter().map(T::from_raw).collect()
+    }
+
+    /// Convolve this CDF with the given density using the FFT, limited to `len` bins.
+    ///
+    /// The products are rounded like [`Value::mul`] does in [`convolve_direct`]
+    /// (Self::convolve_direct) where this is exact, i.e. for products that are rounded up to one
+    /// raw unit or to the density; all other products are raised by the average rounding of
+    /// [`Value::PRODUCT_BIAS`]. The result for `u16` thus deviates from the direct method only by
+    /// the spread of the rounding errors of those products, which grows with the square root of
+    /// their number: a few quanta for small CDFs and up to about 15 quanta for CDFs with a
+    /// thousand bins.
+    fn convolve_fft(&self, density: &[T], len: usize) -> Vec<T> {
+        let density = &density[..density.len().min(len)];
+        let size = (len + density.len()).next_power_of_two();
+        let mut planner = FftPlanner::<f64>::new();
+        let forward = planner.plan_fft_forward(size);
+        let inverse = planner.plan_fft_inverse(size);
+        let zero = Complex::new(0.0, 0.0);
+
+        let cdf: Vec<T> = (0..len).map(|i| self.value_at(i)).collect();
+        let mut groups = BTreeMap::<usize, Vec<T>>::new();
+        for (j, &x) in density.iter().enumerate() {
+            if x != T::default() {
+                groups
+                    .entry(x.density_group())
+                    .or_insert_with(|| vec![T::default(); density.len()])[j] = x;
+            }
+        }
+
+        // the sums of the exactly rounded products, and the spectrum of the approximated ones
+        let mut exact = vec![0.0; len];
+        let mut spectrum = vec![zero; size];
+        for (group, pdf) in groups {
+            // the CDF is non-decreasing, so each kind of rounding applies to a range of its bins
+            let start = |r: ProductRounding| cdf.partition_point(|x| x.product_rounding(group) < r);
+            let (unit, approximate, density) = (
+                start(ProductRounding::Unit),
+                start(ProductRounding::Approximate),
+                start(ProductRounding::Density),
+            );
+
+            let mut sums = vec![0.0];
+            let mut counts = vec![0.0];
+            for &x in &pdf {
+                sums.push(sums.last().unwrap() + x.raw());
+                counts.push(counts.last().unwrap() + (x != T::default()) as u8 as f64);
+            }
+            for (i, exact) in exact.iter_mut().enumerate() {
+                // bin i sums up the products of CDF bins lo..hi with density bins (i-hi, i-lo]
+                let window = |prefix: &[f64], lo: usize, hi: usize| {
+                    let end = (i + 1).saturating_sub(lo).min(pdf.len());
+                    let begin = (i + 1).saturating_sub(hi).min(end);
+                    prefix[end] - prefix[begin]
+                };
+                *exact += window(&counts, unit, approximate)
+                    + window(&counts, approximate, density) * T::PRODUCT_BIAS
+                    + window(&sums, density, len);
+            }
+
+            let mut cdf: Vec<Complex<f64>> = (0..size)
+                .map(|i| match i >= approximate && i < density {
+                    true => Complex::new(cdf[i].raw(), 0.0),
+                    false => zero,
+                })
+                .collect();
+            let mut pdf: Vec<Complex<f64>> = pdf
+                .iter()
+                .map(|&x| Complex::new(x.raw(), 0.0))
+                .chain(std::iter::repeat(zero))
+                .take(size)
+                .collect();
+            forward.process(&mut cdf);
+            forward.process(&mut pdf);
+            for (s, (x, y)) in spectrum.iter_mut().zip(cdf.iter().zip(&pdf)) {
+                *s += x * y;
+            }
+        }
+        inverse.process(&mut spectrum);
+
+        // scale by 1/size for the inverse transform and for the division of the products
+        let scale = 1.0 / (size as f64 * T::PRODUCT_SCALE);
+        let mut last = T::default();
+        spectrum[..len]
+            .iter()
+            .zip(exact)
+            .map(|(x, exact)| {
+                let value = T::from_raw(exact + x.re * scale);
+                // guard against rounding noise breaking monotonicity
+                if value > last {
+                    last = value;
+                }
+                last
+            })
+            .collect()
+    }
+
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let mut ret = None;
+        let len = self.data.len().max(other.data.len());
+        // the eventual completion is compared as if it were the value at infinity
+        let values = (0..len)
+            .map(|i| (self.value_at(i), other.value_at(i)))
+            .chain(std::iter::once((self.completion(), other.completion())));
+        for (l, r) in values {
+            if l < r {
+                if ret == Some(Ordering::Greater) {
+                    return None;
+                }
+                ret = Some(Ordering::Less);
+            } else if l > r {
+                if ret == Some(Ordering::Less) {
+                    return None;
+                }
+                ret = Some(Ordering::Greater);
+            }
+        }
+        ret.or(Some(Ordering::Equal))
+    }
+}
+
+impl std::fmt::Display for Precision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Precision::Fixed16 => write!(f, "fixed16"),
+            Precision::Float64 => write!(f, "float64"),
+        }
+    }
+}
+
 impl std::fmt::Debug for CDF {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CDF")
             .field("data", &self.to_string())
             .field("bin_size", &self.bin_size)
-            .field("len", &self.data.len())
+            .field("len", &self.values.len())
+            .field("truncated", &self.truncated_mass())
+            .field("precision", &self.precision())
             .finish()
     }
 }
 
 impl std::fmt::Display for CDF {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut last_value = 0;
+        let mut last_value = 0.0;
         write!(f, "CDF[")?;
-        for (i, &value) in self.data.iter().enumerate() {
-            let value_f32 = (value as f32 / 65535.0).min(1.0);
-            if value != last_value {
-                if last_value != 0 {
+        for i in 0..self.values.len() {
+            let value_f32 = self.values.value_f32(i);
+            if value_f32 != last_value {
+                if last_value != 0.0 {
                     write!(f, ", ")?;
                 }
                 let x = i as f32 * self.bin_size;
                 write!(f, "({:.4}, {:.4})", x, value_f32)?;
-                last_value = value;
+                last_value = value_f32;
             }
         }
         write!(f, "]")?;
@@ -66,10 +729,65 @@ impl std::fmt::Display for CDF {
     }
 }
 
+/// The textual literal of a CDF, see [`CDF::literal`].
+pub struct CDFLiteral<'a>(&'a CDF);
+
+impl std::fmt::Display for CDFLiteral<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_literal(f)
+    }
+}
+
+impl CDF {
+    /// Get the literal form of this CDF for printing, which can be parsed back without loss,
+    /// e.g. `CDF[(0.1, 0.5), (0.2, 1); bin_size=0.1, bins=5]`.
+    ///
+    /// The literal lists the time and value of each step, followed by the bin size, the number
+    /// of bins, the mass beyond the horizon (if any) and the precision (if not the default).
+    pub fn literal(&self) -> CDFLiteral<'_> {
+        CDFLiteral(self)
+    }
+
+    fn fmt_literal(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // six decimals suffice to recover a fixed point value, which makes for shorter output
+        let value = |v: f64| match self.values {
+            Values::Fixed(_) => {
+                let s = format!("{:.6}", v);
+                s.trim_end_matches('0').trim_end_matches('.').to_owned()
+            }
+            Values::Float(_) => v.to_string(),
+        };
+        write!(f, "CDF[")?;
+        let mut last_value = 0.0;
+        for i in 0..self.values.len() {
+            let current = self.values.value_f64(i);
+            if current != last_value {
+                if last_value != 0.0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "({}, {})", i as f32 * self.bin_size, value(current))?;
+                last_value = current;
+            }
+        }
+        if last_value != 0.0 {
+            write!(f, "; ")?;
+        }
+        write!(f, "bin_size={}, bins={}", self.bin_size, self.values.len())?;
+        let truncated = with_samples!(&self.values, s => s.truncated.to_f64());
+        if truncated > 0.0 {
+            write!(f, ", truncated={}", value(truncated))?;
+        }
+        if self.precision() != Precision::default() {
+            write!(f, ", precision={}", self.precision())?;
+        }
+        write!(f, "]")
+    }
+}
+
 pub struct CDFIterator<'a> {
     cdf: &'a CDF,
     index: usize,
-    last_value: u16,
+    last_value: f32,
     first: bool,
     last: bool,
 }
@@ -82,12 +800,11 @@ impl<'a> Iterator for CDFIterator<'a> {
             self.first = false;
             return Some((0.0, 0.0));
         }
-        while self.index < self.cdf.data.len() {
-            let value = self.cdf.data[self.index];
-            let value_f32 = (value as f32 / 65535.0).min(1.0);
-            if value != self.last_value {
+        while self.index < self.cdf.values.len() {
+            let value_f32 = self.cdf.values.value_f32(self.index);
+            if value_f32 != self.last_value {
                 let x = self.index as f32 * self.cdf.bin_size;
-                self.last_value = value;
+                self.last_value = value_f32;
                 self.index += 1;
                 return Some((x, value_f32));
             }
@@ -95,10 +812,7 @@ impl<'a> Iterator for CDFIterator<'a> {
         }
         if !self.last {
             self.last = true;
-            Some((
-                self.cdf.width(),
-                (self.last_value as f32 / 65535.0).min(1.0),
-            ))
+            Some((self.cdf.width(), self.last_value))
         } else {
             None
         }
@@ -106,11 +820,14 @@ impl<'a> Iterator for CDFIterator<'a> {
 }
 
 impl CDF {
+    /// The default upper bound on the number of bins produced by [`CDF::convolve`].
+    pub const DEFAULT_MAX_SIZE: usize = 10_000;
+
     /// Create a new CDF from a vector of data and a bin size.
     /// The data vector must contain values between 0 and 1, and must be
     /// monotonically increasing.
     pub fn new(data: &[f32], bin_size: f32) -> Result<Self, CDFError> {
-        if !data.iter().all(|&x| x >= 0.0 && x <= 1.0) {
+        if !data.iter().all(|x| (0.0..=1.0).contains(x)) {
             return Err(CDFError::InvalidDataRange);
         }
         if !data.windows(2).all(|w| w[0] <= w[1]) {
@@ -118,16 +835,37 @@ impl CDF {
         }
         let converted_data: Vec<u16> = data.iter().map(|&x| (x * 65535.0) as u16).collect();
         Ok(Self {
-            data: converted_data,
+            values: Values::Fixed(Samples {
+                data: converted_data,
+                truncated: 0,
+            }),
             bin_size,
         })
     }
 
-    pub fn iter(&self) -> CDFIterator {
+    /// Create a new CDF in [`Precision::Float64`] from probabilities and the mass beyond the
+    /// horizon.
+    ///
+    /// The caller must ensure that the data are monotonically increasing and within [0, 1].
+    pub(crate) fn from_probabilities(
+        data: Vec<f64>,
+        truncated: f64,
+        bin_size: f32,
+    ) -> Result<Self, CDFError> {
+        if !(bin_size > 0.0 && bin_size.is_finite()) {
+            return Err(CDFError::InvalidBinSize);
+        }
+        Ok(Self {
+            values: Values::Float(Samples { data, truncated }),
+            bin_size,
+        })
+    }
+
+    pub fn iter(&self) -> CDFIterator<'_> {
         CDFIterator {
             cdf: self,
             index: 0,
-            last_value: 0,
+            last_value: 0.0,
             first: true,
             last: false,
         }
@@ -135,12 +873,291 @@ impl CDF {
 
     /// Get the width of the CDF.
     pub fn width(&self) -> f32 {
-        self.data.len() as f32 * self.bin_size
+        self.values.len() as f32 * self.bin_size
+    }
+
+    /// Get the bin size of the CDF.
+    pub fn bin_size(&self) -> f32 {
+        self.bin_size
+    }
+
+    /// Create a CDF from the steps of a literal, see [`CDF::literal`].
+    ///
+    /// Each step gives the time at which the CDF assumes the given value, the times are rounded
+    /// to the nearest bin.
+    pub(crate) fn from_literal(
+        steps: &[(f64, f64)],
+        bin_size: f32,
+        bins: usize,
+        truncated: f64,
+        precision: Precision,
+    ) -> Result<Self, CDFError> {
+        if !(bin_size > 0.0 && bin_size.is_finite()) {
+            return Err(CDFError::InvalidBinSize);
+        }
+        let last = steps.last().map_or(0.0, |s| s.1);
+        if !steps
+            .iter()
+            .all(|&(t, y)| t >= 0.0 && (0.0..=1.0).contains(&y))
+            // tolerate rounding errors from printing the values in decimal
+            || !(0.0..=1.0 - last + 1e-9).contains(&truncated)
+        {
+            return Err(CDFError::InvalidDataRange);
+        }
+        let indices: Vec<usize> = steps
+            .iter()
+            .map(|&(t, _)| (t / bin_size as f64).round() as usize)
+            .collect();
+        if !steps.windows(2).all(|w| w[0].1 < w[1].1)
+            || !indices.windows(2).all(|w| w[0] < w[1])
+            || indices.last().is_some_and(|&i| i >= bins)
+        {
+            return Err(CDFError::NonMonotonicData);
+        }
+        let mut data = vec![0.0; bins];
+        for (&index, &(_, value)) in indices.iter().zip(steps) {
+            data[index..].fill(value);
+        }
+        let cdf = Self::from_probabilities(data, truncated.min(1.0 - last), bin_size)?;
+        Ok(cdf.with_precision(precision))
+    }
+
+    /// Get the precision with which the values of this CDF are stored.
+    pub fn precision(&self) -> Precision {
+        self.values.precision()
+    }
+
+    /// Convert the CDF to the given precision.
+    ///
+    /// Converting from [`Precision::Fixed16`] to [`Precision::Float64`] is lossless.
+    pub fn with_precision(&self, precision: Precision) -> CDF {
+        let values = match precision {
+            Precision::Fixed16 => Values::Fixed(self.values.to_fixed().into_owned()),
+            Precision::Float64 => Values::Float(self.values.to_float().into_owned()),
+        };
+        CDF {
+            values,
+            bin_size: self.bin_size,
+        }
+    }
+
+    /// Resample the CDF onto a grid with the given bin size, keeping its width (rounded up to
+    /// whole bins).
+    ///
+    /// Resampling onto a finer grid whose bin size evenly divides the current one is lossless,
+    /// otherwise the `rounding` determines in which direction the CDF is shifted.
+    pub fn resample(&self, bin_size: f32, rounding: Rounding) -> Result<CDF, CDFError> {
+        if !(bin_size > 0.0 && bin_size.is_finite()) {
+            return Err(CDFError::InvalidBinSize);
+        }
+        if bin_size == self.bin_size {
+            return Ok(self.clone());
+        }
+        // tolerance (in units of old bins) for rounding errors in the bin size ratio
+        const EPSILON: f64 = 1e-4;
+        let ratio = bin_size as f64 / self.bin_size as f64;
+        let len = (self.values.len() as f64 / ratio - EPSILON).ceil().max(0.0) as usize;
+        let index = |j: usize| match rounding {
+            Rounding::Conservative => (j as f64 * ratio + EPSILON).floor() as usize,
+            Rounding::Optimistic => ((j + 1) as f64 * ratio - EPSILON).ceil().max(1.0) as usize - 1,
+        };
+        Ok(CDF {
+            values: map_samples!(&self.values, s => s.resample(len, index)),
+            bin_size,
+        })
+    }
+
+    /// Resample both CDFs onto the finer of their two grids, if they differ.
+    ///
+    /// Returns the two resampled CDFs, which share the same bin size.
+    pub fn align(&self, other: &CDF, rounding: Rounding) -> Result<(CDF, CDF), CDFError> {
+        let bin_size = self.bin_size.min(other.bin_size);
+        Ok((
+            self.resample(bin_size, rounding)?,
+            other.resample(bin_size, rounding)?,
+        ))
+    }
+
+    /// Get the probability mass that completes beyond the horizon of this CDF.
+    ///
+    /// This mass is not included in the CDF's values, but it is not failure either: the outcome
+    /// does occur, only later than the CDF is able to represent.
+    pub fn truncated_mass(&self) -> f32 {
+        with_samples!(&self.values, s => s.truncated.to_f32())
+    }
+
+    /// Get the probability of the outcome occurring eventually, including the mass beyond the
+    /// horizon.
+    pub fn completion_probability(&self) -> f32 {
+        with_samples!(&self.values, s => s.completion().to_f32())
+    }
+
+    /// Get the probability of the outcome never occurring, i.e. the attenuation of the outcome.
+    pub fn failure_probability(&self) -> f32 {
+        1.0 - self.completion_probability()
+    }
+
+    /// Get the probability of the outcome having occurred by the given time.
+    ///
+    /// Beyond the horizon this is the last value of the CDF, i.e. it does not include the
+    /// truncated mass.
+    pub fn probability_at(&self, time: f32) -> f32 {
+        if time < 0.0 {
+            return 0.0;
+        }
+        let index = (time as f64 / self.bin_size as f64 + 1e-4).floor();
+        self.values.value_f32(index.min(usize::MAX as f64) as usize)
+    }
+
+    /// Draw the time at which the outcome occurs, using the given source of randomness.
+    ///
+    /// The outcome occurs at the start of the bin in which the CDF increases, consistent with
+    /// [`CDF::step`]. Returns `None` if the outcome fails; mass beyond the horizon is drawn as
+    /// infinity since the exact time is not known.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<f32> {
+        self.sample_f64(rng).map(|t| t as f32)
+    }
+
+    /// Draw an infinite sequence of samples from a generator with the given seed, see
+    /// [`CDF::sample`].
+    pub fn samples(&self, seed: u64) -> impl Iterator<Item = Option<f32>> + '_ {
+        let mut rng = StdRng::seed_from_u64(seed);
+        std::iter::repeat_with(move || self.sample(&mut rng))
+    }
+
+    /// Like [`CDF::sample`], but without losing precision for large times.
+    pub(crate) fn sample_f64<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<f64> {
+        let uniform = rng.gen::<f64>();
+        let index = with_samples!(&self.values, s => s.draw(uniform))?;
+        if index < self.values.len() {
+            Some(index as f64 * self.bin_size as f64)
+        } else {
+            Some(f64::INFINITY)
+        }
+    }
+
+    /// Get the earliest time by which the outcome has occurred with the given probability, or
+    /// `None` if that probability is not reached within the horizon.
+    pub fn quantile(&self, probability: f32) -> Option<f32> {
+        self.values
+            .quantile_index(probability as f64)
+            .map(|i| i as f32 * self.bin_size)
+    }
+
+    /// Get the probability mass and time of each bin with non-zero mass within the horizon.
+    fn masses(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        let bin_size = self.bin_size as f64;
+        (0..self.values.len()).filter_map(move |i| {
+            let previous = if i == 0 {
+                0.0
+            } else {
+                self.values.value_f64(i - 1)
+            };
+            let mass = self.values.value_f64(i) - previous;
+            (mass > 0.0).then_some((mass, i as f64 * bin_size))
+        })
+    }
+
+    /// Get the mean time of the outcome, given that it occurs within the horizon.
+    ///
+    /// Returns `None` if there is no probability mass within the horizon.
+    pub fn mean(&self) -> Option<f32> {
+        let (total, sum) = self
+            .masses()
+            .fold((0.0, 0.0), |(t, s), (m, x)| (t + m, s + m * x));
+        (total > 0.0).then(|| (sum / total) as f32)
+    }
+
+    /// Get the variance of the time of the outcome, given that it occurs within the horizon.
+    ///
+    /// Returns `None` if there is no probability mass within the horizon.
+    pub fn variance(&self) -> Option<f32> {
+        let mean = self.mean()? as f64;
+        let (total, sum) = self.masses().fold((0.0, 0.0), |(t, s), (m, x)| {
+            (t + m, s + m * (x - mean) * (x - mean))
+        });
+        Some((sum / total) as f32)
+    }
+
+    /// Compute the statistical summary of this CDF.
+    pub fn summary(&self) -> Summary {
+        Summary {
+            mean: self.mean(),
+            variance: self.variance(),
+            p50: self.quantile(0.5),
+            p95: self.quantile(0.95),
+            p99: self.quantile(0.99),
+            p99_9: self.quantile(0.999),
+            completion: self.completion_probability(),
+            truncated: self.truncated_mass(),
+        }
+    }
+
+    /// Get the Kolmogorov–Smirnov distance to another CDF, i.e. the largest vertical distance
+    /// between both curves.
+    ///
+    /// The eventual completion probabilities are included, so that two CDFs that differ only in
+    /// their mass beyond the horizon are not considered equal. CDFs of different bin sizes are
+    /// compared on the finer grid, see [`CDF::align`].
+    pub fn kolmogorov_smirnov(&self, other: &CDF) -> Result<f32, CDFError> {
+        let (left, right) = self.align(other, Rounding::Conservative)?;
+        let (left, right) = (left.values.to_float(), right.values.to_float());
+        let len = left.data.len().max(right.data.len());
+        Ok((0..len)
+            .map(|i| (left.value_at(i) - right.value_at(i)).abs())
+            .chain([(left.completion() - right.completion()).abs()])
+            .fold(0.0, f64::max) as f32)
+    }
+
+    /// Get the Wasserstein (or earth mover's) distance to another CDF, i.e. the area between
+    /// both curves.
+    ///
+    /// The area is taken up to the longer of both horizons, beyond which the CDFs are no longer
+    /// known; for CDFs of equal completion probability without truncated mass this is the
+    /// average time by which the outcomes differ. CDFs of different bin sizes are compared on
+    /// the finer grid, see [`CDF::align`].
+    pub fn wasserstein(&self, other: &CDF) -> Result<f32, CDFError> {
+        let (left, right) = self.align(other, Rounding::Conservative)?;
+        let bin_size = left.bin_size;
+        let (left, right) = (left.values.to_float(), right.values.to_float());
+        let len = left.data.len().max(right.data.len());
+        let area: f64 = (0..len)
+            .map(|i| (left.value_at(i) - right.value_at(i)).abs())
+            .sum();
+        Ok((area * bin_size as f64) as f32)
+    }
+
+    /// Get the largest horizontal (time) distance between this and another CDF for all
+    /// probabilities up to the given one.
+    ///
+    /// Returns `None` if either CDF does not reach the given probability within its horizon.
+    /// The bin sizes may differ since only quantiles are compared.
+    pub fn max_time_gap(&self, other: &CDF, probability: f32) -> Option<f32> {
+        let probability = probability as f64;
+        let gap = |p: f64| {
+            let left = self.values.quantile_index(p)? as f64 * self.bin_size as f64;
+            let right = other.values.quantile_index(p)? as f64 * other.bin_size as f64;
+            Some((left - right).abs())
+        };
+        // the quantile functions are constant between the values of either CDF
+        let steps = |values: &Values| {
+            (0..values.len())
+                .map(|i| values.value_f64(i))
+                .filter(|&p| p > 0.0 && p < probability)
+                .collect::<Vec<_>>()
+        };
+        let mut max = gap(probability)?;
+        for p in steps(&self.values).into_iter().chain(steps(&other.values)) {
+            max = max.max(gap(p)?);
+        }
+        Some(max as f32)
     }
 
     /// Create a step function CDF from a vector of (x, y) pairs.
     /// The x values must be greater than 0 and must be strictly monotonically increasing.
     /// The y values must be from (0, 1] and must be strictly monotonically increasing.
+    ///
+    /// Points beyond the given number of bins are recorded as truncated mass.
     pub fn step(points: &[(f32, f32)], bin_size: f32, bins: usize) -> Result<Self, CDFError> {
         if !points.iter().all(|&(x, y)| x >= 0.0 && y > 0.0 && y <= 1.0) {
             return Err(CDFError::InvalidDataRange);
@@ -152,120 +1169,288 @@ impl CDF {
             return Err(CDFError::NonMonotonicData);
         }
         let mut data = vec![0u16; bins];
+        let mut completion = 0;
         for &(x, y) in points {
             let index = (x / bin_size).floor() as usize;
-            data[index] = to_int(y);
+            completion = to_int(y);
+            if index < bins {
+                data[index] = completion;
+            }
+        }
+        for i in 1..data.len() {
+            if data[i] == 0 {
+                data[i] = data[i - 1];
+            }
+        }
+        let truncated = completion - data.last().copied().unwrap_or(0);
+        Ok(Self {
+            values: Values::Fixed(Samples { data, truncated }),
+            bin_size,
+        })
+    }
+
+    /// The approximate number of bins used by [`CDF::from_samples`] when choosing the bin size.
+    pub const EMPIRICAL_BINS: usize = 1000;
+
+    /// Create the empirical CDF of the given measured durations.
+    ///
+    /// The `failed` samples (e.g. timeouts or lost messages) count towards the total, so they
+    /// become the failure probability of the CDF. Without a `bin_size`, the smallest 1-2-5 value
+    /// that fits all samples into [`CDF::EMPIRICAL_BINS`] bins is used; otherwise the horizon is
+    /// capped at [`CDF::DEFAULT_MAX_SIZE`] bins and later samples become truncated mass.
+    ///
+    /// The result uses [`Precision::Float64`], see [`CDF::confidence_band`] for how far it may
+    /// be trusted.
+    pub fn from_samples(
+        samples: &[f32],
+        failed: usize,
+        bin_size: Option<f32>,
+    ) -> Result<Self, CDFError> {
+        let total = samples.len() + failed;
+        if total == 0 {
+            return Err(CDFError::InvalidParameter("no samples given".to_owned()));
+        }
+        if !samples.iter().all(|x| *x >= 0.0 && x.is_finite()) {
+            return Err(CDFError::InvalidDataRange);
+        }
+        let max = samples.iter().copied().fold(0.0f32, f32::max);
+        let bin_size = bin_size.unwrap_or_else(|| nice_bin_size(max / Self::EMPIRICAL_BINS as f32));
+        if !(bin_size > 0.0 && bin_size.is_finite()) {
+            return Err(CDFError::InvalidBinSize);
+        }
+        let bins = ((max / bin_size).floor() as usize).min(Self::DEFAULT_MAX_SIZE - 1) + 1;
+        let mut counts = vec![0usize; bins];
+        let mut beyond = 0;
+        for &x in samples {
+            match counts.get_mut((x / bin_size).floor() as usize) {
+                Some(count) => *count += 1,
+                None => beyond += 1,
+            }
+        }
+        let mut cumulative = 0;
+        let data = counts
+            .into_iter()
+            .map(|count| {
+                cumulative += count;
+                cumulative as f64 / total as f64
+            })
+            .collect();
+        Self::from_probabilities(data, beyond as f64 / total as f64, bin_size)
+    }
+
+    /// Get the Dvoretzky–Kiefer–Wolfowitz confidence band around an empirical CDF.
+    ///
+    /// Given that the CDF was estimated from `samples` measurements (including failed ones),
+    /// the true CDF lies between the returned lower and upper CDFs everywhere (including at
+    /// infinity, i.e. the completion probability) with at least the given `confidence`.
+    /// The band has half-width `sqrt(ln(2 / (1 - confidence)) / (2 * samples))`.
+    pub fn confidence_band(&self, samples: usize, confidence: f32) -> Result<(CDF, CDF), CDFError> {
+        if !(confidence > 0.0 && confidence < 1.0) {
+            return Err(CDFError::InvalidFraction);
+        }
+        if samples == 0 {
+            return Err(CDFError::InvalidParameter("no samples given".to_owned()));
         }
-        for i in 1..data.len() {
-            if data[i] == 0 {
-                data[i] = data[i - 1];
-            }
+        let epsilon = ((2.0 / (1.0 - confidence as f64)).ln() / (2.0 * samples as f64)).sqrt();
+        let values = self.values.to_float();
+        let band = |shift: f64| {
+            let data = values
+                .data
+                .iter()
+                .map(|x| (x + shift).clamp(0.0, 1.0))
+                .collect::<Vec<_>>();
+            let completion = (values.completion() + shift).clamp(0.0, 1.0);
+            let last = data.last().copied().unwrap_or(0.0);
+            CDF::from_probabilities(data, completion - last, self.bin_size)
+        };
+        Ok((band(-epsilon)?, band(epsilon)?))
+    }
+
+    /// Combine two CDFs of the same bin size, using the higher of their precisions.
+    fn combine(
+        &self,
+        other: &CDF,
+        fixed: impl FnOnce(&Samples<u16>, &Samples<u16>) -> Samples<u16>,
+        float: impl FnOnce(&Samples<f64>, &Samples<f64>) -> Samples<f64>,
+    ) -> Result<CDF, CDFError> {
+        if self.bin_size != other.bin_size {
+            return Err(CDFError::BinSizeMismatch);
         }
-        Ok(Self { data, bin_size })
+        let values = match (&self.values, &other.values) {
+            (Values::Fixed(l), Values::Fixed(r)) => Values::Fixed(fixed(l, r)),
+            (l, r) => Values::Float(float(&l.to_float(), &r.to_float())),
+        };
+        Ok(CDF {
+            values,
+            bin_size: self.bin_size,
+        })
     }
 
     /// Combine two CDFs by choosing between them, using the given fraction as the probability for
     /// the first CDF.
     pub fn choice(&self, fraction: f32, other: &CDF) -> Result<CDF, CDFError> {
-        if self.bin_size != other.bin_size {
-            return Err(CDFError::BinSizeMismatch);
-        }
-        if self.data.len() != other.data.len() {
-            return Err(CDFError::LengthMismatch);
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(CDFError::InvalidFraction);
         }
-        if fraction < 0.0 || fraction > 1.0 {
+        self.combine(
+            other,
+            |l, r| l.choice(fraction, r),
+            |l, r| l.choice(fraction, r),
+        )
+    }
+
+    /// Combine several CDFs of the same bin size by choosing among them, using the given weights
+    /// as their relative probabilities.
+    ///
+    /// This is the same as nesting [`CDF::choice`], but computed in one pass. The result uses
+    /// the highest of the precisions of the CDFs.
+    pub fn mixture(branches: &[(f32, CDF)]) -> Result<CDF, CDFError> {
+        let total = branches.iter().map(|(w, _)| *w as f64).sum::<f64>();
+        if branches.iter().any(|(w, _)| !(w.is_finite() && *w >= 0.0)) || total <= 0.0 {
             return Err(CDFError::InvalidFraction);
         }
-        let my_fraction = to_int(fraction);
-        let fraction = 65535 - my_fraction;
-        let combined_data: Vec<u16> = self
-            .data
+        let bin_size = branches[0].1.bin_size;
+        if branches.iter().any(|(_, cdf)| cdf.bin_size != bin_size) {
+            return Err(CDFError::BinSizeMismatch);
+        }
+        let fixed = branches
             .iter()
-            .zip(&other.data)
-            .map(|(&x, &y)| {
-                mul(x, my_fraction)
-                    .checked_add(mul(y, fraction))
-                    .expect("addition overflow")
+            .map(|(w, cdf)| match &cdf.values {
+                Values::Fixed(s) => Some((*w as f64 / total, s)),
+                Values::Float(_) => None,
             })
-            .collect();
-        Ok(CDF {
-            data: combined_data,
-            bin_size: self.bin_size,
-        })
+            .collect::<Option<Vec<_>>>();
+        let values = match fixed {
+            Some(fixed) => Values::Fixed(Samples::mixture(&fixed)),
+            None => {
+                let float = branches
+                    .iter()
+                    .map(|(w, cdf)| (*w as f64 / total, cdf.values.to_float()))
+                    .collect::<Vec<_>>();
+                let float = float.iter().map(|(p, s)| (*p, &**s)).collect::<Vec<_>>();
+                Values::Float(Samples::mixture(&float))
+            }
+        };
+        Ok(CDF { values, bin_size })
     }
 
-    /// Combine two CDFs by universal quantification, meaning that both outcomes must occur.
-    pub fn for_all(&self, other: &CDF) -> Result<CDF, CDFError> {
-        if self.bin_size != other.bin_size {
-            return Err(CDFError::BinSizeMismatch);
+    /// Combine several CDFs of the same bin size into the CDF of the time at which at least `k`
+    /// of the independent outcomes have occurred, i.e. of their `k`-th order statistic.
+    ///
+    /// With `k` equal to the number of CDFs this is the universal quantification over all of
+    /// them, with `k = 1` the existential one. The result uses the highest of the precisions of
+    /// the CDFs.
+    pub fn k_of_n(k: usize, cdfs: &[CDF]) -> Result<CDF, CDFError> {
+        if !(1..=cdfs.len()).contains(&k) {
+            return Err(CDFError::InvalidParameter(format!(
+                "k must be between 1 and the number of outcomes {}, found {}",
+                cdfs.len(),
+                k
+            )));
         }
-        if self.data.len() != other.data.len() {
-            return Err(CDFError::LengthMismatch);
+        let bin_size = cdfs[0].bin_size;
+        if cdfs.iter().any(|cdf| cdf.bin_size != bin_size) {
+            return Err(CDFError::BinSizeMismatch);
         }
-        let multiplied_data: Vec<u16> = self
-            .data
+        let fixed = cdfs
             .iter()
-            .zip(&other.data)
-            .map(|(&x, &y)| mul(x, y))
-            .collect();
-        Ok(CDF {
-            data: multiplied_data,
-            bin_size: self.bin_size,
-        })
+            .map(|cdf| match &cdf.values {
+                Values::Fixed(s) => Some(s),
+                Values::Float(_) => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        let values = match fixed {
+            Some(fixed) => Values::Fixed(Samples::k_of_n(k, &fixed)),
+            None => {
+                let float = cdfs
+                    .iter()
+                    .map(|cdf| cdf.values.to_float())
+                    .collect::<Vec<_>>();
+                let float = float.iter().map(|s| &**s).collect::<Vec<_>>();
+                Values::Float(Samples::k_of_n(k, &float))
+            }
+        };
+        Ok(CDF { values, bin_size })
     }
 
-    /// Combine two CDFs by existential quantification, meaning that at least one of the outcomes
-    pub fn for_some(&self, other: &CDF) -> Result<CDF, CDFError> {
-        if self.bin_size != other.bin_size {
-            return Err(CDFError::BinSizeMismatch);
+    /// Give up on the outcome if it has not occurred by the given deadline, i.e. move all
+    /// probability mass beyond the deadline into failure.
+    ///
+    /// If a fallback is given, it is started at the deadline whenever the outcome has not
+    /// occurred by then, e.g. for retrying after a timeout. The deadline is rounded down to the
+    /// grid of this CDF, which the fallback must share. Mass beyond the horizon is taken to miss
+    /// the deadline, since its timing is unknown.
+    ///
+    /// The result is limited to [`CDF::DEFAULT_MAX_SIZE`] bins.
+    pub fn timeout(&self, deadline: f32, fallback: Option<&CDF>) -> Result<CDF, CDFError> {
+        self.timeout_with_max_size(deadline, fallback, Self::DEFAULT_MAX_SIZE)
+    }
+
+    /// Give up on the outcome like [`CDF::timeout`], limiting the result to at most `max_size`
+    /// bins (or the width of this CDF, if larger).
+    ///
+    /// The probability mass that is cut off by this limit, e.g. all of the fallback for a
+    /// deadline beyond it, is recorded as truncated mass.
+    pub fn timeout_with_max_size(
+        &self,
+        deadline: f32,
+        fallback: Option<&CDF>,
+        max_size: usize,
+    ) -> Result<CDF, CDFError> {
+        if !(deadline >= 0.0 && deadline.is_finite()) {
+            return Err(CDFError::InvalidParameter(format!(
+                "deadline must be non-negative, found {}",
+                deadline
+            )));
         }
-        if self.data.len() != other.data.len() {
-            return Err(CDFError::LengthMismatch);
+        // same rounding as in `probability_at`
+        let index = (deadline as f64 / self.bin_size as f64 + 1e-4).floor() as usize;
+        match fallback {
+            None => Ok(CDF {
+                values: map_samples!(&self.values, s => s.timeout(index, None, max_size)),
+                bin_size: self.bin_size,
+            }),
+            Some(fallback) => self.combine(
+                fallback,
+                |l, r| l.timeout(index, Some(r), max_size),
+                |l, r| l.timeout(index, Some(r), max_size),
+            ),
         }
-        let multiplied_data: Vec<u16> = self
-            .data
-            .iter()
-            .zip(&other.data)
-            .map(|(&x, &y)| {
-                u16::try_from(
-                    (x as u32 + y as u32)
-                        .checked_sub(mul(x, y) as u32)
-                        .expect("subtraction underflow during for_some"),
-                )
-                .expect("overflow during for_some")
-            })
-            .collect();
-        Ok(CDF {
-            data: multiplied_data,
-            bin_size: self.bin_size,
-        })
+    }
+
+    /// Combine two CDFs by universal quantification, meaning that both outcomes must occur.
+    pub fn for_all(&self, other: &CDF) -> Result<CDF, CDFError> {
+        self.combine(other, Samples::for_all, Samples::for_all)
+    }
+
+    /// Combine two CDFs by existential quantification, meaning that at least one of the outcomes
+    pub fn for_some(&self, other: &CDF) -> Result<CDF, CDFError> {
+        self.combine(other, Samples::for_some, Samples::for_some)
     }
 
     /// Convolve two CDFs, which is equivalent to taking the sum of all possible outcomes of the
     /// two CDFs. This describes the distribution of the sum of two independent random variables.
+    ///
+    /// The width of the result is the sum of both widths, limited to [`CDF::DEFAULT_MAX_SIZE`] bins.
     pub fn convolve(&self, other: &CDF) -> Result<CDF, CDFError> {
-        if self.bin_size != other.bin_size {
-            return Err(CDFError::BinSizeMismatch);
-        }
-        if self.data.len() != other.data.len() {
-            return Err(CDFError::LengthMismatch);
-        }
-        let len = self.data.len();
-        let mut convolved_data: Vec<u16> = vec![0; len];
-        for i in 0..len {
-            for j in 0..len - i {
-                let other = if j == 0 {
-                    other.data[j]
-                } else {
-                    other.data[j] - other.data[j - 1]
-                };
-                convolved_data[i + j] += mul(self.data[i], other);
-            }
-        }
-        Ok(CDF {
-            data: convolved_data,
-            bin_size: self.bin_size,
-        })
+        self.convolve_with_max_size(other, Self::DEFAULT_MAX_SIZE)
+    }
+
+    /// Convolve two CDFs like [`CDF::convolve`], limiting the result to at most `max_size` bins.
+    ///
+    /// The probability mass that is cut off by this limit is recorded as truncated mass.
+    ///
+    /// For large CDFs the convolution is computed using the FFT, which models the rounding of the
+    /// direct method and agrees with it up to a few quanta of the CDF values.
+    pub fn convolve_with_max_size(&self, other: &CDF, max_size: usize) -> Result<CDF, CDFError> {
+        let len = (self.values.len() + other.values.len()).min(max_size);
+        self.combine(other, |l, r| l.convolve(r, len), |l, r| l.convolve(r, len))
+    }
+}
+
+impl PartialEq for CDF {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
     }
 }
 
@@ -274,24 +1459,27 @@ impl PartialOrd for CDF {
         if self.bin_size != other.bin_size {
             return None;
         }
-        let mut ret = None;
-        for (l, r) in self.data.iter().zip(&other.data) {
-            if l < r {
-                if ret == Some(Ordering::Greater) {
-                    return None;
-                }
-                ret = Some(Ordering::Less);
-            } else if l > r {
-                if ret == Some(Ordering::Less) {
-                    return None;
-                }
-                ret = Some(Ordering::Greater);
-            }
+        match (&self.values, &other.values) {
+            (Values::Fixed(l), Values::Fixed(r)) => l.partial_cmp(r),
+            (l, r) => l.to_float().partial_cmp(&r.to_float()),
         }
-        ret.or(Some(Ordering::Equal))
     }
 }
 
+/// Decide whether to convolve with the given density using the FFT.
+///
+/// The direct method costs one multiplication per non-zero density bin and result bin, which
+/// is cheap for step functions but quadratic for smooth distributions.
+fn use_fft<T: Value>(density: &[T], len: usize) -> bool {
+    let non_zero = density
+        .iter()
+        .take(len)
+        .filter(|&&x| x != T::default())
+        .count();
+    let size = (len + density.len().min(len)).next_power_of_two();
+    non_zero * len > FFT_THRESHOLD * size * size.trailing_zeros().max(1) as usize
+}
+
 fn mul(x: u16, y: u16) -> u16 {
     ((x as u32 * y as u32 + 65535) >> 16) as u16
 }
@@ -300,14 +1488,35 @@ fn to_int(x: f32) -> u16 {
     (x * 65536.0 + 0.5).min(65535.0) as u16
 }
 
+/// Round up to the next value of the form 1, 2 or 5 times a power of ten.
+fn nice_bin_size(min: f32) -> f32 {
+    if !(min > 0.0 && min.is_finite()) {
+        return 1.0;
+    }
+    let decade = 10f64.powf((min as f64).log10().floor());
+    let nice = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * decade)
+        .find(|&x| x >= min as f64 * (1.0 - 1e-6))
+        .unwrap_or(10.0 * decade);
+    nice as f32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn fixed(cdf: &CDF) -> &Samples<u16> {
+        match &cdf.values {
+            Values::Fixed(s) => s,
+            Values::Float(_) => panic!("expected fixed point CDF"),
+        }
+    }
+
     #[test]
     fn test_new() {
         let cdf = CDF::new(&[0.0, 0.25, 0.5, 0.75, 1.0], 0.25).unwrap();
-        assert_eq!(cdf.data, vec![0, 16383, 32767, 49151, 65535]);
+        assert_eq!(fixed(&cdf).data, vec![0, 16383, 32767, 49151, 65535]);
         assert_eq!(cdf.bin_size, 0.25);
 
         let cdf = CDF::new(&[0.0, 0.25, 0.5, 0.75, 1.1], 0.25);
@@ -317,6 +1526,57 @@ mod tests {
         assert_eq!(cdf, Err(CDFError::NonMonotonicData));
     }
 
+    #[test]
+    fn test_resample() {
+        let cdf = CDF::new(&[0.0, 0.2, 0.4, 0.6, 0.8, 1.0], 1.0).unwrap();
+        assert_eq!(
+            cdf.resample(2.0, Rounding::Conservative).unwrap(),
+            CDF::new(&[0.0, 0.4, 0.8], 2.0).unwrap()
+        );
+        assert_eq!(
+            cdf.resample(2.0, Rounding::Optimistic).unwrap(),
+            CDF::new(&[0.2, 0.6, 1.0], 2.0).unwrap()
+        );
+        assert_eq!(
+            cdf.resample(4.0, Rounding::Conservative).unwrap(),
+            CDF::new(&[0.0, 0.8], 4.0).unwrap()
+        );
+        assert_eq!(
+            cdf.resample(4.0, Rounding::Optimistic).unwrap(),
+            CDF::new(&[0.6, 1.0], 4.0).unwrap()
+        );
+        assert_eq!(
+            cdf.resample(0.0, Rounding::Conservative),
+            Err(CDFError::InvalidBinSize)
+        );
+    }
+
+    #[test]
+    fn test_resample_finer() {
+        let cdf = CDF::new(&[0.0, 0.5, 1.0], 0.001).unwrap();
+        for rounding in [Rounding::Conservative, Rounding::Optimistic] {
+            let fine = cdf.resample(0.0001, rounding).unwrap();
+            assert_eq!(fine.bin_size(), 0.0001);
+            assert_eq!(fine.width(), 0.003);
+            assert_eq!(fine.to_string(), "CDF[(0.0010, 0.5000), (0.0020, 1.0000)]");
+            assert_eq!(fine.resample(0.001, rounding).unwrap(), cdf);
+        }
+    }
+
+    #[test]
+    fn test_align() {
+        let coarse = CDF::new(&[0.0, 1.0], 1.0).unwrap();
+        let fine = CDF::new(&[0.0, 0.5, 1.0], 0.5).unwrap();
+        assert_eq!(coarse.for_all(&fine), Err(CDFError::BinSizeMismatch));
+        let (left, right) = coarse.align(&fine, Rounding::Conservative).unwrap();
+        assert_eq!(left.bin_size(), 0.5);
+        assert_eq!(right, fine);
+        assert_eq!(
+            left.for_all(&right).unwrap(),
+            CDF::new(&[0.0, 0.0, 1.0, 1.0], 0.5).unwrap()
+        );
+    }
+
     #[test]
     fn test_choice() {
         let left = CDF::new(&[0.0, 0.0, 0.5, 1.0, 1.0], 0.25).unwrap();
@@ -327,6 +1587,114 @@ mod tests {
         assert_eq!(added, CDF::new(&[0.0, 0.0, 0.5, 1.0, 1.0], 0.25).unwrap());
     }
 
+    #[test]
+    fn test_mixture() {
+        let left = CDF::new(&[0.0, 0.0, 0.5, 1.0, 1.0], 0.25).unwrap();
+        let middle = CDF::new(&[0.0, 1.0], 0.25).unwrap();
+        let right = CDF::new(&[0.5], 0.25).unwrap();
+        let mixed = CDF::mixture(&[
+            (2.0, left.clone()),
+            (1.0, middle.clone()),
+            (1.0, right.clone()),
+        ])
+        .unwrap();
+        let nested = left
+            .choice(0.5, &middle.choice(0.5, &right).unwrap())
+            .unwrap();
+        assert!(mixed.kolmogorov_smirnov(&nested).unwrap() < 1e-4);
+        assert_eq!(mixed.width(), 1.25);
+        assert!((mixed.completion_probability() - 0.875).abs() < 1e-4);
+
+        let float = CDF::mixture(&[
+            (1.0, left.with_precision(Precision::Float64)),
+            (1.0, middle),
+        ])
+        .unwrap();
+        assert_eq!(float.precision(), Precision::Float64);
+        assert_eq!(
+            CDF::mixture(&[(0.0, left.clone())]),
+            Err(CDFError::InvalidFraction)
+        );
+        assert_eq!(
+            CDF::mixture(&[(1.0, left), (1.0, CDF::new(&[1.0], 0.5).unwrap())]),
+            Err(CDFError::BinSizeMismatch)
+        );
+    }
+
+    #[test]
+    fn test_k_of_n() {
+        let left = CDF::new(&[0.0, 0.5, 1.0], 0.25).unwrap();
+        let middle = CDF::new(&[0.2, 0.4], 0.25).unwrap();
+        let right = CDF::new(&[0.0, 0.0, 0.0, 1.0], 0.25).unwrap();
+        let all = CDF::k_of_n(3, &[left.clone(), middle.clone(), right.clone()]).unwrap();
+        let nested = left.for_all(&middle.for_all(&right).unwrap()).unwrap();
+        assert!(all.kolmogorov_smirnov(&nested).unwrap() < 1e-4);
+        let some = CDF::k_of_n(1, &[left.clone(), middle.clone(), right.clone()]).unwrap();
+        let nested = left.for_some(&middle.for_some(&right).unwrap()).unwrap();
+        assert!(some.kolmogorov_smirnov(&nested).unwrap() < 1e-4);
+
+        // binomial distribution of three identical outcomes
+        let two = CDF::k_of_n(2, &[left.clone(), left.clone(), left.clone()]).unwrap();
+        assert!((two.probability_at(0.25) - 0.5).abs() < 1e-4);
+        assert!((two.probability_at(0.5) - 1.0).abs() < 1e-4);
+
+        assert!(CDF::k_of_n(0, std::slice::from_ref(&left)).is_err());
+        assert!(CDF::k_of_n(2, std::slice::from_ref(&left)).is_err());
+        assert_eq!(
+            CDF::k_of_n(1, &[left, CDF::new(&[1.0], 0.5).unwrap()]),
+            Err(CDFError::BinSizeMismatch)
+        );
+    }
+
+    #[test]
+    fn test_timeout() {
+        let cdf = CDF::new(&[0.0, 0.5, 0.5, 0.9], 0.25).unwrap();
+        let cut = cdf.timeout(0.25, None).unwrap();
+        assert_eq!(cut, CDF::new(&[0.0, 0.5], 0.25).unwrap());
+        assert_eq!(cut.truncated_mass(), 0.0);
+        assert_eq!(cdf.timeout(2.0, None).unwrap(), cdf);
+
+        let fallback = CDF::new(&[0.0, 1.0], 0.25).unwrap();
+        let retried = cdf.timeout(0.5, Some(&fallback)).unwrap();
+        assert_eq!(retried.width(), 1.0);
+        assert!((retried.probability_at(0.5) - 0.5).abs() < 1e-4);
+        assert!((retried.probability_at(0.75) - 1.0).abs() < 1e-4);
+
+        // mass beyond the horizon of the fallback stays beyond the horizon
+        let later = CDF::from_probabilities(vec![0.0, 0.5], 0.5, 0.25).unwrap();
+        let retried = cdf.timeout(0.25, Some(&later)).unwrap();
+        assert!((retried.completion_probability() - 1.0).abs() < 1e-4);
+        assert!((retried.truncated_mass() - 0.25).abs() < 1e-4);
+
+        // a deadline far beyond the horizon leaves the fallback beyond the limit
+        let retried = cdf.timeout_with_max_size(1e30, Some(&fallback), 8).unwrap();
+        assert_eq!(retried.width(), 2.0);
+        assert!((retried.probability_at(2.0) - 0.9).abs() < 1e-4);
+        assert!((retried.truncated_mass() - 0.1).abs() < 1e-4);
+        let retried = cdf.timeout(1e6, Some(&fallback)).unwrap();
+        assert_eq!(retried.width(), CDF::DEFAULT_MAX_SIZE as f32 * 0.25);
+        assert!((retried.completion_probability() - 1.0).abs() < 1e-4);
+
+        assert!(cdf.timeout(-1.0, None).is_err());
+        assert!(cdf.timeout(f32::NAN, None).is_err());
+        assert_eq!(
+            cdf.timeout(0.5, Some(&CDF::new(&[1.0], 0.5).unwrap())),
+            Err(CDFError::BinSizeMismatch)
+        );
+    }
+
+    #[test]
+    fn test_choice_different_widths() {
+        let left = CDF::new(&[0.0, 0.0, 0.5, 1.0, 1.0, 1.0], 0.25).unwrap();
+        let right = CDF::new(&[0.0, 1.0], 0.25).unwrap();
+        let added = left.choice(0.7, &right).unwrap();
+        assert_eq!(added.width(), 1.5);
+        assert_eq!(
+            added,
+            CDF::new(&[0.0, 0.3, 0.65, 1.0, 1.0, 1.0], 0.25).unwrap()
+        );
+    }
+
     #[test]
     fn test_convolve_step() {
         let left = CDF::new(&[0.0, 1.0, 1.0, 1.0, 1.0], 1.0).unwrap();
@@ -334,8 +1702,9 @@ mod tests {
         let convolved = left.convolve(&right).unwrap();
         assert_eq!(
             convolved,
-            CDF::new(&[0.0, 0.0, 0.0, 1.0, 1.0], 1.0).unwrap()
+            CDF::new(&[0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0], 1.0).unwrap()
         );
+        assert_eq!(convolved.width(), 10.0);
     }
 
     #[test]
@@ -347,6 +1716,154 @@ mod tests {
             convolved,
             CDF::new(&[0.0, 0.0, 0.0, 0.18, 0.3, 0.72, 1.0], 1.0).unwrap()
         );
+        assert_eq!(convolved.width(), 14.0);
+    }
+
+    #[test]
+    fn test_convolve_different_widths() {
+        let left = CDF::new(&[0.0, 0.5, 1.0], 1.0).unwrap();
+        let right = CDF::step(&[(4.0, 1.0)], 1.0, 5).unwrap();
+        let convolved = left.convolve(&right).unwrap();
+        assert_eq!(convolved.width(), 8.0);
+        assert_eq!(
+            convolved,
+            CDF::new(&[0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 1.0, 1.0], 1.0).unwrap()
+        );
+        assert_eq!(right.convolve(&left).unwrap(), convolved);
+    }
+
+    #[test]
+    fn test_convolve_max_size() {
+        let left = CDF::new(&[0.0, 0.5, 1.0], 1.0).unwrap();
+        let right = CDF::new(&[0.0, 0.0, 0.0, 1.0], 1.0).unwrap();
+        let convolved = left.convolve_with_max_size(&right, 5).unwrap();
+        assert_eq!(convolved.width(), 5.0);
+        assert_eq!(convolved.to_string(), "CDF[(4.0000, 0.5000)]");
+        assert_eq!(format!("{:.4}", convolved.truncated_mass()), "0.5000");
+    }
+
+    #[test]
+    fn test_convolve_fft() {
+        let smooth = |len: usize, scale: f32| {
+            let data: Vec<f32> = (0..len)
+                .map(|i| 1.0 - (-(i as f32) / scale).exp())
+                .collect();
+            CDF::new(&data, 0.01).unwrap()
+        };
+        let left = smooth(1000, 50.0);
+        let right = smooth(700, 120.0);
+        let density = fixed(&right).density();
+        for len in [10, 500, 1700] {
+            let direct = fixed(&left).convolve_direct(&density, len);
+            let fft = fixed(&left).convolve_fft(&density, len);
+            assert_eq!(direct.len(), len);
+            assert_eq!(fft.len(), len);
+            // the FFT only approximates the rounding of products that the direct method does not
+            // round exactly, which leaves the spread of their rounding errors
+            for (d, f) in direct.iter().zip(&fft) {
+                assert!(d.abs_diff(*f) <= 16, "direct {d} fft {f} at len {len}");
+            }
+            assert!(fft.windows(2).all(|w| w[0] <= w[1]));
+        }
+        assert!(use_fft(&density, 1700));
+        let step = CDF::step(&[(1.0, 0.5), (3.0, 1.0)], 0.01, 700).unwrap();
+        assert!(!use_fft(&fixed(&step).density(), 1700));
+
+        let convolved = left.convolve(&right).unwrap();
+        assert_eq!(convolved.width(), 17.0);
+        assert_eq!(convolved.truncated_mass(), 0.0);
+
+        // the FFT in floating point agrees with the direct method up to rounding errors
+        let left = left.with_precision(Precision::Float64);
+        let right = right.with_precision(Precision::Float64);
+        let Values::Float(samples) = &left.values else {
+            panic!("expected floating point CDF")
+        };
+        let Values::Float(other) = &right.values else {
+            panic!("expected floating point CDF")
+        };
+        let density = other.density();
+        let direct = samples.convolve_direct(&density, 1700);
+        let fft = samples.convolve_fft(&density, 1700);
+        for (d, f) in direct.iter().zip(&fft) {
+            assert!((d - f).abs() < 1e-9, "direct {d} fft {f}");
+        }
+    }
+
+    #[test]
+    fn test_precision() {
+        let fixed_cdf = CDF::new(&[0.0, 0.3, 0.3, 1.0], 1.0).unwrap();
+        assert_eq!(fixed_cdf.precision(), Precision::Fixed16);
+        let float = fixed_cdf.with_precision(Precision::Float64);
+        assert_eq!(float.precision(), Precision::Float64);
+        assert_eq!(float, fixed_cdf);
+        assert_eq!(float.to_string(), fixed_cdf.to_string());
+        assert_eq!(float.with_precision(Precision::Fixed16), fixed_cdf);
+        assert_eq!(
+            fixed(&float.with_precision(Precision::Fixed16)).data,
+            fixed(&fixed_cdf).data
+        );
+
+        // mixing precisions yields the higher one
+        let convolved = fixed_cdf.convolve(&float).unwrap();
+        assert_eq!(convolved.precision(), Precision::Float64);
+        assert_eq!(
+            convolved.to_string(),
+            "CDF[(2.0000, 0.0900), (4.0000, 0.5100), (6.0000, 1.0000)]"
+        );
+        assert_eq!(
+            fixed_cdf.for_some(&float).unwrap().to_string(),
+            "CDF[(1.0000, 0.5100), (3.0000, 1.0000)]"
+        );
+        assert_eq!(
+            float.choice(0.25, &fixed_cdf).unwrap().to_string(),
+            "CDF[(1.0000, 0.3000), (3.0000, 1.0000)]"
+        );
+    }
+
+    #[test]
+    fn test_precision_serde() {
+        let cdf = CDF::step(&[(1.0, 0.5), (5.0, 1.0)], 1.0, 3).unwrap();
+        let json = serde_json::to_string(&cdf).unwrap();
+        assert_eq!(
+            json,
+            r#"{"data":[0,32768,32768],"truncated":32767,"bin_size":1.0}"#
+        );
+        let back: CDF = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.precision(), Precision::Fixed16);
+        assert_eq!(back, cdf);
+
+        let float = cdf.with_precision(Precision::Float64);
+        let json = serde_json::to_string(&float).unwrap();
+        let back: CDF = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.precision(), Precision::Float64);
+        assert_eq!(back, float);
+    }
+
+    #[test]
+    fn test_display_literal() {
+        let cdf = CDF::step(&[(1.0, 0.5), (5.0, 1.0)], 1.0, 3).unwrap();
+        assert_eq!(
+            cdf.literal().to_string(),
+            "CDF[(1, 0.500008); bin_size=1, bins=3, truncated=0.499992]"
+        );
+        assert_eq!(cdf.to_string(), "CDF[(1.0000, 0.5000)]");
+
+        let float = CDF::step(&[(0.02, 0.1), (0.05, 0.7)], 0.01, 10)
+            .unwrap()
+            .with_precision(Precision::Float64)
+            .convolve(&CDF::step(&[(0.01, 1.0 / 3.0)], 0.01, 5).unwrap())
+            .unwrap();
+        let empty = CDF::new(&[], 0.25).unwrap();
+        assert_eq!(empty.literal().to_string(), "CDF[bin_size=0.25, bins=0]");
+        for cdf in [cdf, float, empty] {
+            let back: CDF = cdf.literal().to_string().parse().unwrap();
+            assert_eq!(back.precision(), cdf.precision());
+            assert_eq!(
+                serde_json::to_string(&back).unwrap(),
+                serde_json::to_string(&cdf).unwrap()
+            );
+        }
     }
 
     #[test]
@@ -366,6 +1883,95 @@ mod tests {
     }
 
     #[test]
+    fn test_step_truncated() {
+        let cdf = CDF::step(&[(1.0, 0.25), (2.0, 0.5), (7.0, 0.75)], 1.0, 5).unwrap();
+        assert_eq!(fixed(&cdf).data, vec![0, 16384, 32768, 32768, 32768]);
+        assert_eq!(fixed(&cdf).truncated, 16384);
+        assert_ne!(cdf, CDF::new(&[0.0, 0.25, 0.5, 0.5, 0.5], 1.0).unwrap());
+    }
+
+    #[test]
+    fn test_convolve_truncated() {
+        let left = CDF::new(&[0.0, 0.5, 1.0], 1.0).unwrap();
+        let right = CDF::new(&[0.0, 0.0, 0.0, 0.5], 1.0).unwrap();
+        let convolved = left.convolve(&right).unwrap();
+        assert_eq!(format!("{:.4}", convolved.truncated_mass()), "0.0000");
+        let convolved = left.convolve_with_max_size(&right, 5).unwrap();
+        assert_eq!(
+            convolved,
+            CDF::step(&[(4.0, 0.25), (9.0, 0.5)], 1.0, 5).unwrap()
+        );
+
+        // truncated mass of the operands is propagated
+        let right = CDF::step(&[(1.0, 0.5), (10.0, 1.0)], 1.0, 3).unwrap();
+        let convolved = left.convolve(&right).unwrap();
+        assert_eq!(format!("{:.4}", convolved.truncated_mass()), "0.5000");
+        let convolved = convolved.convolve(&left).unwrap();
+        assert_eq!(format!("{:.4}", convolved.truncated_mass()), "0.5000");
+        assert_eq!(
+            convolved.to_string(),
+            "CDF[(3.0000, 0.1250), (4.0000, 0.3750), (5.0000, 0.5000)]"
+        );
+    }
+
+    #[test]
+    fn test_combinators_truncated() {
+        let left = CDF::step(&[(1.0, 0.5), (10.0, 1.0)], 1.0, 3).unwrap();
+        let right = CDF::step(&[(1.0, 0.5)], 1.0, 3).unwrap();
+        assert_eq!(
+            format!("{:.4}", left.choice(0.5, &right).unwrap().truncated_mass()),
+            "0.2500"
+        );
+        assert_eq!(
+            format!("{:.4}", left.for_all(&right).unwrap().truncated_mass()),
+            "0.2500"
+        );
+        let for_some = left.for_some(&right).unwrap();
+        assert_eq!(for_some.to_string(), "CDF[(1.0000, 0.7500)]");
+        assert_eq!(format!("{:.4}", for_some.truncated_mass()), "0.2500");
+    }
+
+    #[test]
+    fn test_statistics() {
+        let cdf = CDF::new(&[0.0, 0.25, 0.5, 0.5, 0.75], 0.5).unwrap();
+        assert_eq!(format!("{:.4}", cdf.mean().unwrap()), "1.1667");
+        assert_eq!(format!("{:.4}", cdf.variance().unwrap()), "0.3889");
+        assert_eq!(cdf.quantile(0.25), Some(0.5));
+        assert_eq!(cdf.quantile(0.5), Some(1.0));
+        assert_eq!(cdf.quantile(0.75), Some(2.0));
+        assert_eq!(cdf.quantile(0.8), None);
+        assert_eq!(cdf.probability_at(-1.0), 0.0);
+        assert_eq!(format!("{:.4}", cdf.probability_at(0.7)), "0.2500");
+        assert_eq!(format!("{:.4}", cdf.probability_at(1.0)), "0.5000");
+        assert_eq!(format!("{:.4}", cdf.probability_at(10.0)), "0.7500");
+        assert_eq!(format!("{:.4}", cdf.failure_probability()), "0.2500");
+
+        let cdf = CDF::step(&[(1.0, 0.5), (5.0, 0.9)], 1.0, 3).unwrap();
+        let summary = cdf.summary();
+        assert_eq!(summary.mean, Some(1.0));
+        assert_eq!(summary.variance, Some(0.0));
+        assert_eq!(summary.p50, Some(1.0));
+        assert_eq!(summary.p95, None);
+        assert_eq!(format!("{:.4}", summary.completion), "0.9000");
+        assert_eq!(format!("{:.4}", summary.truncated), "0.4000");
+
+        let empty = CDF::new(&[0.0, 0.0], 1.0).unwrap();
+        assert_eq!(empty.mean(), None);
+        assert_eq!(empty.variance(), None);
+        assert_eq!(empty.quantile(0.0), Some(0.0));
+    }
+
+    #[test]
+    fn test_eq_beyond_horizon() {
+        let short = CDF::new(&[0.0, 0.5, 1.0], 1.0).unwrap();
+        let long = CDF::new(&[0.0, 0.5, 1.0, 1.0, 1.0], 1.0).unwrap();
+        assert_eq!(short, long);
+        assert_ne!(short, CDF::new(&[0.0, 0.5, 1.0, 1.0, 1.0], 0.5).unwrap());
+        assert!(CDF::new(&[0.0, 0.5, 0.9], 1.0).unwrap() < long);
+    }
+
+    #[test]
+    #[allow(clippy::neg_cmp_op_on_partial_ord)]
     fn partial_ord() {
         let left = CDF::new(&[0.0, 0.3, 0.3, 1.0], 1.0).unwrap();
         let right = CDF::new(&[0.0, 0.0, 0.6, 1.0], 1.0).unwrap();
@@ -393,4 +1999,124 @@ mod tests {
         assert!(right >= bottom);
         assert!(bottom <= right);
     }
+
+    #[test]
+    fn test_from_samples() {
+        let cdf = CDF::from_samples(&[0.1, 0.3, 0.3, 0.25], 1, Some(0.1)).unwrap();
+        assert_eq!(cdf.precision(), Precision::Float64);
+        assert_eq!(
+            cdf.to_string(),
+            "CDF[(0.1000, 0.2000), (0.2000, 0.4000), (0.3000, 0.8000)]"
+        );
+        assert_eq!(format!("{:.4}", cdf.failure_probability()), "0.2000");
+
+        // automatic bin size covers all samples
+        let cdf = CDF::from_samples(&[0.0, 0.0123, 0.4567], 0, None).unwrap();
+        assert_eq!(cdf.bin_size(), 0.0005);
+        assert_eq!(cdf.completion_probability(), 1.0);
+        assert_eq!(cdf.truncated_mass(), 0.0);
+        assert_eq!(cdf.quantile(0.5), Some(0.012));
+
+        // samples beyond the capped horizon become truncated mass
+        let cdf = CDF::from_samples(&[0.5, 1e6], 0, Some(1.0)).unwrap();
+        assert_eq!(cdf.width(), CDF::DEFAULT_MAX_SIZE as f32);
+        assert_eq!(cdf.truncated_mass(), 0.5);
+        // levels reached exactly count as reached despite the f32 probabilities
+        let samples: Vec<f32> = (0..1000).map(|i| i as f32 * 0.001).collect();
+        let cdf = CDF::from_samples(&samples[..100], 0, Some(0.001)).unwrap();
+        assert_eq!(format!("{:.4}", cdf.quantile(0.99).unwrap()), "0.0980");
+        let cdf = CDF::from_samples(&samples, 0, Some(0.001)).unwrap();
+        assert_eq!(format!("{:.4}", cdf.summary().p99_9.unwrap()), "0.9980");
+        let cdf = CDF::from_samples(&[0.0, 1e30], 0, Some(1e-6)).unwrap();
+        assert_eq!(cdf.truncated_mass(), 0.5);
+
+        assert_eq!(
+            CDF::from_samples(&[], 0, None),
+            Err(CDFError::InvalidParameter("no samples given".to_owned()))
+        );
+        assert_eq!(
+            CDF::from_samples(&[-1.0], 0, None),
+            Err(CDFError::InvalidDataRange)
+        );
+        assert_eq!(
+            CDF::from_samples(&[1.0], 0, Some(0.0)),
+            Err(CDFError::InvalidBinSize)
+        );
+    }
+
+    #[test]
+    fn test_confidence_band() {
+        let cdf = CDF::from_samples(&[0.1, 0.3, 0.3, 0.25], 1, Some(0.1)).unwrap();
+        // epsilon = sqrt(ln(20) / 1000) = 0.0547
+        let (lower, upper) = cdf.confidence_band(500, 0.9).unwrap();
+        assert_eq!(
+            lower.to_string(),
+            "CDF[(0.1000, 0.1453), (0.2000, 0.3453), (0.3000, 0.7453)]"
+        );
+        assert_eq!(
+            upper.to_string(),
+            "CDF[(0.0000, 0.0547), (0.1000, 0.2547), (0.2000, 0.4547), (0.3000, 0.8547)]"
+        );
+        assert_eq!(format!("{:.4}", upper.completion_probability()), "0.8547");
+        assert!(lower <= cdf && cdf <= upper);
+
+        // few samples make for a wide band, clamped to [0, 1]
+        let (lower, upper) = cdf.confidence_band(5, 0.95).unwrap();
+        assert_eq!(lower.to_string(), "CDF[(0.3000, 0.1926)]");
+        assert_eq!(upper.completion_probability(), 1.0);
+
+        assert_eq!(cdf.confidence_band(5, 1.0), Err(CDFError::InvalidFraction));
+    }
+
+    #[test]
+    fn test_distances() {
+        let a = CDF::new(&[0.0, 0.5, 1.0], 1.0).unwrap();
+        let b = CDF::new(&[0.5, 1.0], 1.0).unwrap();
+        assert_eq!(
+            format!("{:.4}", a.kolmogorov_smirnov(&b).unwrap()),
+            "0.5000"
+        );
+        assert_eq!(format!("{:.4}", a.wasserstein(&b).unwrap()), "1.0000");
+        assert_eq!(a.kolmogorov_smirnov(&a).unwrap(), 0.0);
+        assert_eq!(a.wasserstein(&a).unwrap(), 0.0);
+
+        // mass beyond the horizon is taken into account
+        let late = CDF::step(&[(1.0, 0.5), (5.0, 1.0)], 1.0, 3).unwrap();
+        assert_eq!(
+            format!("{:.4}", a.kolmogorov_smirnov(&late).unwrap()),
+            "0.5000"
+        );
+        assert_eq!(format!("{:.4}", a.wasserstein(&late).unwrap()), "0.5000");
+        let failing = CDF::new(&[0.0, 0.5], 1.0).unwrap();
+        assert_eq!(
+            format!("{:.4}", late.kolmogorov_smirnov(&failing).unwrap()),
+            "0.5000"
+        );
+
+        // different bin sizes are compared on the finer grid
+        let fine = CDF::new(&[0.0, 0.0, 0.5, 0.5, 1.0], 0.5).unwrap();
+        assert_eq!(a.kolmogorov_smirnov(&fine).unwrap(), 0.0);
+        assert_eq!(a.wasserstein(&fine).unwrap(), 0.0);
+        let b_fine = CDF::new(&[0.5, 0.5, 1.0], 0.5).unwrap();
+        assert_eq!(b_fine.kolmogorov_smirnov(&b).unwrap(), 0.0);
+        assert_eq!(format!("{:.4}", a.wasserstein(&b_fine).unwrap()), "1.0000");
+    }
+
+    #[test]
+    fn test_max_time_gap() {
+        let a = CDF::new(&[0.0, 0.5, 1.0], 1.0).unwrap();
+        let b = CDF::new(&[0.5, 1.0], 1.0).unwrap();
+        assert_eq!(a.max_time_gap(&b, 1.0), Some(1.0));
+        assert_eq!(a.max_time_gap(&a, 1.0), Some(0.0));
+
+        // different bin sizes and crossing curves
+        let c = CDF::step(&[(0.5, 0.5), (3.0, 1.0)], 0.5, 8).unwrap();
+        assert_eq!(a.max_time_gap(&c, 0.5), Some(0.5));
+        assert_eq!(a.max_time_gap(&c, 1.0), Some(1.0));
+        assert_eq!(c.max_time_gap(&a, 1.0), Some(1.0));
+
+        let improper = CDF::new(&[0.0, 0.9], 1.0).unwrap();
+        assert_eq!(a.max_time_gap(&improper, 0.9), Some(1.0));
+        assert_eq!(a.max_time_gap(&improper, 0.95), None);
+    }
 }
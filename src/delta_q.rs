@@ -1,4 +1,10 @@
+use crate::load::waiting_time;
 use crate::CDFError;
+use crate::Precision;
+use crate::Requirement;
+use crate::Rounding;
+use crate::Slack;
+use crate::Verdict;
 use crate::CDF;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
@@ -8,7 +14,149 @@ use std::fmt::{self, Display};
 pub enum DeltaQError {
     CDFError(CDFError),
     NameError(String),
+    /// A name depends on itself, the path lists the names of the cycle ending in the first one.
+    Cycle(Vec<String>),
     BlackBox,
+    /// An error that occurred at the given location in an expression.
+    Located(Box<DeltaQError>, Location),
+}
+
+impl DeltaQError {
+    /// Get the error without its location.
+    pub fn cause(&self) -> &DeltaQError {
+        match self {
+            DeltaQError::Located(error, _) => error.cause(),
+            error => error,
+        }
+    }
+
+    /// Get the location of the error, if known.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            DeltaQError::Located(_, location) => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Record that this error occurred within the operand of an expression given by `step`.
+    pub(crate) fn within(self, step: Step) -> Self {
+        match self {
+            DeltaQError::Located(error, mut location) => {
+                if location.name.is_none() {
+                    location.path.insert(0, step);
+                }
+                DeltaQError::Located(error, location)
+            }
+            error => DeltaQError::Located(
+                Box::new(error),
+                Location {
+                    name: None,
+                    path: vec![step],
+                },
+            ),
+        }
+    }
+
+    /// Drop the path of an error that is not attributed to a name yet, locating it at the root
+    /// of the current expression.
+    pub(crate) fn at_root(self) -> Self {
+        match self {
+            DeltaQError::Located(error, location) if location.name.is_none() => *error,
+            error => error,
+        }
+    }
+
+    /// Record that this error occurred within the definition of the given name, unless it
+    /// already occurred in the definition of another name referred to from there.
+    pub(crate) fn in_definition(self, name: &str) -> Self {
+        match self {
+            DeltaQError::Located(error, mut location) => {
+                location.name.get_or_insert_with(|| name.to_owned());
+                DeltaQError::Located(error, location)
+            }
+            error => DeltaQError::Located(
+                Box::new(error),
+                Location {
+                    name: Some(name.to_owned()),
+                    path: Vec::new(),
+                },
+            ),
+        }
+    }
+}
+
+/// The operators of DeltaQ expressions that have operands, see [`Step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Operator {
+    Seq,
+    Choice,
+    ForAll,
+    ForSome,
+    Repeat,
+    Uses,
+    ChoiceN,
+    ForAllN,
+    ForSomeN,
+    KOfN,
+    Timeout,
+}
+
+/// A step from an expression into one of its operands, given by its index.
+///
+/// For sequence, choice and the quantifiers the index is 0 for the left and 1 for the right
+/// operand; for repetitions it is 0 for the base and 1 for the template. A resource usage
+/// annotation has only the operand 0, a timeout has the operand 0 and the fallback 1, while the
+/// operands of n-ary operators are numbered from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Step {
+    pub operator: Operator,
+    pub index: usize,
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = match (self.operator, self.index) {
+            (
+                Operator::ChoiceN | Operator::ForAllN | Operator::ForSomeN | Operator::KOfN,
+                index,
+            ) => {
+                return write!(f, "branch {} of {:?}", index, self.operator);
+            }
+            (Operator::Repeat, 0) => "base",
+            (Operator::Repeat, _) => "template",
+            (Operator::Uses, _) | (Operator::Timeout, 0) => "operand",
+            (Operator::Timeout, _) => "fallback",
+            (_, 0) => "left",
+            (_, _) => "right",
+        };
+        write!(f, "{} of {:?}", operand, self.operator)
+    }
+}
+
+/// The location of a sub-expression: the path leading to it from the root of the definition
+/// of `name`, or from the root of the evaluated expression if `name` is `None`.
+///
+/// It is printed innermost step first, e.g. `left of Seq, right of Choice in `model3``.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Location {
+    pub name: Option<String>,
+    pub path: Vec<Step>,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.path.iter().rev().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", step)?;
+        }
+        match (&self.name, self.path.is_empty()) {
+            (Some(name), true) => write!(f, "`{}`", name),
+            (Some(name), false) => write!(f, " in `{}`", name),
+            (None, _) => Ok(()),
+        }
+    }
 }
 
 impl std::error::Error for DeltaQError {}
@@ -18,7 +166,9 @@ impl Display for DeltaQError {
         match self {
             DeltaQError::CDFError(e) => write!(f, "CDF error: {}", e),
             DeltaQError::NameError(name) => write!(f, "Name error: {}", name),
+            DeltaQError::Cycle(path) => write!(f, "Cycle: {}", path.join(" -> ")),
             DeltaQError::BlackBox => write!(f, "Black box encountered"),
+            DeltaQError::Located(error, location) => write!(f, "{} at {}", error, location),
         }
     }
 }
@@ -29,15 +179,140 @@ impl From<CDFError> for DeltaQError {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
-#[serde(from = "BTreeMap<String, DeltaQ>", into = "BTreeMap<String, DeltaQ>")]
+/// Settings for evaluating recursive definitions by iterating to a fixpoint.
+///
+/// A recursive name `r = F(r)` is evaluated by starting from an outcome that never occurs and
+/// repeatedly computing `F` of the previous result, which spells out the recursion up to
+/// increasing depths. The iteration stops after `max_depth` steps or as soon as the CDF changes
+/// by no more than `tolerance` (in Kolmogorov-Smirnov distance, see
+/// [`CDF::kolmogorov_smirnov`]).
+///
+/// The mass of the runs that recurse deeper than that is counted as failure in the result; it
+/// is reported as residual by [`EvaluationContext::residual`], computed by doing the same
+/// iteration starting from an outcome that occurs immediately.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fixpoint {
+    pub max_depth: usize,
+    pub tolerance: f32,
+}
+
+/// The named DeltaQ expressions of a model, together with their computed CDFs and the
+/// requirements attached to them.
+///
+/// The serialized form contains the expressions together with the requirements and the
+/// capacities of resources, but neither computed values nor evaluation settings.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(from = "SerializedContext", into = "SerializedContext")]
 pub struct EvaluationContext {
     ctx: BTreeMap<String, (DeltaQ, Option<CDF>)>,
     deps: BTreeMap<String, BTreeSet<String>>,
+    requirements: BTreeMap<String, Requirement>,
+    max_size: usize,
+    precision: Precision,
+    /// The names currently being evaluated, for detecting cycles.
+    evaluating: Vec<String>,
+    fixpoint: Option<Fixpoint>,
+    /// The current approximations of recursive names during fixpoint iteration.
+    approximations: BTreeMap<String, CDF>,
+    /// The residual mass of the computed values of recursive names.
+    residuals: BTreeMap<String, f32>,
+    /// Whether to use the upper instead of the lower bound of envelopes.
+    optimistic: bool,
+    /// The capacity of each resource, i.e. the amount it can serve per unit of time.
+    capacities: BTreeMap<String, f32>,
+    /// The utilisation of resources while evaluating under load, which adds waiting times to
+    /// the outcomes using them, see [`waiting_time`].
+    utilisation: BTreeMap<String, f32>,
+}
+
+impl Default for EvaluationContext {
+    fn default() -> Self {
+        Self {
+            ctx: BTreeMap::new(),
+            deps: BTreeMap::new(),
+            requirements: BTreeMap::new(),
+            max_size: CDF::DEFAULT_MAX_SIZE,
+            precision: Precision::default(),
+            evaluating: Vec::new(),
+            fixpoint: None,
+            approximations: BTreeMap::new(),
+            residuals: BTreeMap::new(),
+            optimistic: false,
+            capacities: BTreeMap::new(),
+            utilisation: BTreeMap::new(),
+        }
+    }
 }
 
 impl EvaluationContext {
-    pub fn put(&mut self, name: String, delta_q: DeltaQ) {
+    /// Get the maximal number of bins of CDFs computed by sequential composition.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Set the maximal number of bins of CDFs computed by sequential composition.
+    ///
+    /// This invalidates all computed values.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.invalidate();
+    }
+
+    /// Get the precision in which CDFs are computed.
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Set the precision in which CDFs are computed; all CDF literals are converted to this
+    /// precision before being combined.
+    ///
+    /// This invalidates all computed values.
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.precision = precision;
+        self.invalidate();
+    }
+
+    /// Get the settings for evaluating recursive definitions, if enabled.
+    pub fn fixpoint(&self) -> Option<Fixpoint> {
+        self.fixpoint
+    }
+
+    /// Enable or disable the evaluation of recursive definitions by fixpoint iteration.
+    ///
+    /// While enabled, [`put`](Self::put) accepts definitions that create cycles. This
+    /// invalidates all computed values.
+    pub fn set_fixpoint(&mut self, fixpoint: Option<Fixpoint>) {
+        self.fixpoint = fixpoint;
+        self.invalidate();
+    }
+
+    /// Get the residual mass of the given recursive name, i.e. the probability of recursing
+    /// deeper than its fixpoint iteration went, which is counted as failure in its CDF.
+    ///
+    /// This is only available after evaluating the name and as long as the computed value is
+    /// valid, and only for names that are part of a cycle.
+    pub fn residual(&self, name: &str) -> Option<f32> {
+        self.ctx.get(name).and_then(|(_, cdf)| cdf.as_ref())?;
+        self.residuals.get(name).copied()
+    }
+
+    fn invalidate(&mut self) {
+        for (_, cdf) in self.ctx.values_mut() {
+            cdf.take();
+        }
+    }
+
+    /// Define the given name, replacing any previous definition.
+    ///
+    /// The definition is rejected with [`DeltaQError::Cycle`] if it would make the name depend
+    /// on itself, unless fixpoint iteration is enabled (see [`set_fixpoint`](Self::set_fixpoint)).
+    pub fn put(&mut self, name: String, delta_q: DeltaQ) -> Result<(), DeltaQError> {
+        let deps = delta_q.deps();
+        if self.fixpoint.is_none() {
+            if let Some(path) = self.find_cycle(&name, &deps) {
+                return Err(DeltaQError::Cycle(path));
+            }
+        }
         // first remove all computed values that depend on this name
         let mut to_remove = vec![name.clone()];
         while let Some(name) = to_remove.pop() {
@@ -50,8 +325,100 @@ impl EvaluationContext {
                 }
             }
         }
-        self.deps.insert(name.clone(), delta_q.deps());
+        self.deps.insert(name.clone(), deps);
         self.ctx.insert(name, (delta_q, None));
+        Ok(())
+    }
+
+    /// Find the cycle that defining `name` with the given dependencies would create, as the
+    /// path of names leading from `name` back to itself.
+    fn find_cycle(&self, name: &str, deps: &BTreeSet<String>) -> Option<Vec<String>> {
+        let mut path = vec![name.to_owned()];
+        let mut visited = BTreeSet::new();
+        self.path_to(name, deps, &mut path, &mut visited)
+            .then_some(path)
+    }
+
+    fn depends_on_itself(&self, name: &str) -> bool {
+        self.deps
+            .get(name)
+            .is_some_and(|deps| self.find_cycle(name, deps).is_some())
+    }
+
+    /// Check whether the given name refers to `target`, directly or indirectly.
+    fn depends_on(&self, name: &str, target: &str) -> bool {
+        name == target
+            || self.deps.get(name).is_some_and(|deps| {
+                self.path_to(target, deps, &mut Vec::new(), &mut BTreeSet::new())
+            })
+    }
+
+    /// Evaluate the recursive definition of `name` by fixpoint iteration, returning the CDF
+    /// together with the residual mass.
+    fn iterate(&mut self, name: &str, delta_q: &DeltaQ) -> Result<(CDF, f32), DeltaQError> {
+        let fixpoint = self.fixpoint.expect("fixpoint iteration is enabled");
+        let bin_size = delta_q
+            .min_bin_size(self, &mut BTreeSet::new())
+            .ok_or_else(|| {
+                CDFError::InvalidParameter(format!(
+                    "recursive definition of {} contains no CDF",
+                    name
+                ))
+            })?;
+        // never occurring yields the result, occurring immediately the bound for the residual
+        let mut lower = CDF::new(&[], bin_size)?.with_precision(self.precision);
+        let mut upper = CDF::new(&[1.0], bin_size)?.with_precision(self.precision);
+        for depth in 1..=fixpoint.max_depth {
+            let next_lower = self.approximate(name, lower.clone(), delta_q)?;
+            upper = self.approximate(name, upper, delta_q)?;
+            let change = next_lower.kolmogorov_smirnov(&lower)?;
+            lower = next_lower;
+            if change <= fixpoint.tolerance {
+                tracing::info!("Fixpoint of {} reached at depth {}", name, depth);
+                break;
+            }
+        }
+        let residual = upper.completion_probability() - lower.completion_probability();
+        Ok((lower, residual.max(0.0)))
+    }
+
+    /// Evaluate the definition of the recursive `name`, using the given CDF for its occurrences.
+    fn approximate(
+        &mut self,
+        name: &str,
+        approximation: CDF,
+        delta_q: &DeltaQ,
+    ) -> Result<CDF, DeltaQError> {
+        self.approximations.insert(name.to_owned(), approximation);
+        let result = delta_q.eval(self);
+        self.approximations.remove(name);
+        result
+    }
+
+    /// Depth-first search for `target` among the given names and their dependencies,
+    /// appending the names leading there to `path`.
+    fn path_to(
+        &self,
+        target: &str,
+        names: &BTreeSet<String>,
+        path: &mut Vec<String>,
+        visited: &mut BTreeSet<String>,
+    ) -> bool {
+        for name in names {
+            path.push(name.clone());
+            if name == target {
+                return true;
+            }
+            if visited.insert(name.clone()) {
+                if let Some(deps) = self.deps.get(name) {
+                    if self.path_to(target, deps, path, visited) {
+                        return true;
+                    }
+                }
+            }
+            path.pop();
+        }
+        false
     }
 
     pub fn remove(&mut self, name: &str) -> Option<DeltaQ> {
@@ -68,6 +435,7 @@ impl EvaluationContext {
             }
         }
         self.deps.remove(name);
+        self.requirements.remove(name);
         self.ctx.remove(name).map(|(dq, _)| dq)
     }
 
@@ -79,9 +447,193 @@ impl EvaluationContext {
         DeltaQ::name(name).eval(self)
     }
 
+    /// Evaluate the given name for both bounds of the envelopes of assumed outcomes (see
+    /// [`Assumption::Envelope`]) it depends on, returning the pessimistic and the optimistic
+    /// CDF.
+    ///
+    /// The pessimistic CDF is the one returned by [`eval`](Self::eval); both are the same if no
+    /// envelope is involved. This relies on all operators preserving the order of outcomes, i.e.
+    /// an outcome that is faster in every part is faster as a whole.
+    pub fn eval_envelope(&mut self, name: &str) -> Result<(CDF, CDF), DeltaQError> {
+        let lower = self.eval(name)?;
+        let mut optimistic = self.clone();
+        optimistic.optimistic = true;
+        optimistic.invalidate();
+        let upper = optimistic.eval(name)?;
+        Ok((lower, upper))
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (&String, &DeltaQ)> {
         self.ctx.iter().map(|(k, (v, _))| (k, v))
     }
+
+    /// Attach a requirement to the given name, replacing any previous one.
+    ///
+    /// The name does not need to be defined yet.
+    pub fn set_requirement(&mut self, name: String, requirement: Requirement) {
+        self.requirements.insert(name, requirement);
+    }
+
+    pub fn remove_requirement(&mut self, name: &str) -> Option<Requirement> {
+        self.requirements.remove(name)
+    }
+
+    pub fn requirement(&self, name: &str) -> Option<&Requirement> {
+        self.requirements.get(name)
+    }
+
+    /// Set the capacity of the given resource, i.e. the amount of it that can be consumed per
+    /// unit of time, for evaluating under load (see [`eval_under_load`](Self::eval_under_load)).
+    ///
+    /// The resource does not need to be used yet.
+    pub fn set_capacity(&mut self, resource: String, capacity: f32) {
+        self.capacities.insert(resource, capacity);
+    }
+
+    pub fn remove_capacity(&mut self, resource: &str) -> Option<f32> {
+        self.capacities.remove(resource)
+    }
+
+    pub fn capacity(&self, resource: &str) -> Option<f32> {
+        self.capacities.get(resource).copied()
+    }
+
+    pub fn capacities(&self) -> impl Iterator<Item = (&String, f32)> {
+        self.capacities.iter().map(|(k, v)| (k, *v))
+    }
+
+    /// Set the utilisation of resources used for adding waiting times, see
+    /// [`eval_under_load`](Self::eval_under_load).
+    ///
+    /// This invalidates all computed values.
+    pub(crate) fn set_utilisation(&mut self, utilisation: BTreeMap<String, f32>) {
+        self.utilisation = utilisation;
+        self.invalidate();
+    }
+
+    /// Evaluate the given name and check it against its requirement, if it has one.
+    pub fn check(&mut self, name: &str) -> Result<Option<Verdict>, DeltaQError> {
+        let cdf = self.eval(name)?;
+        Ok(self.requirements.get(name).map(|req| req.check(&cdf)))
+    }
+
+    /// Evaluate the given name and compute its slack with respect to its requirement, if it has
+    /// one.
+    pub fn slack(&mut self, name: &str) -> Result<Option<Slack>, DeltaQError> {
+        let cdf = self.eval(name)?;
+        Ok(self.requirements.get(name).map(|req| req.slack(&cdf)))
+    }
+
+    /// Evaluate all names that have a requirement and check them against it.
+    pub fn check_all(&mut self) -> BTreeMap<String, Result<Verdict, DeltaQError>> {
+        let names: Vec<_> = self.requirements.keys().cloned().collect();
+        names
+            .into_iter()
+            .map(|name| {
+                let verdict = self
+                    .eval(&name)
+                    .map(|cdf| self.requirements[&name].check(&cdf));
+                (name, verdict)
+            })
+            .collect()
+    }
+
+    /// Evaluate all names and report the outcome for each of them.
+    ///
+    /// Unlike [`eval`](Self::eval) this does not stop at the first error, so every broken
+    /// definition is found at once. Names are evaluated in dependency order, i.e. each name
+    /// after the names it refers to.
+    pub fn eval_all(&mut self) -> BTreeMap<String, Report> {
+        let mut order = Vec::new();
+        let mut visited = BTreeSet::new();
+        for name in self.ctx.keys() {
+            self.visit(name, &mut visited, &mut order);
+        }
+        order
+            .into_iter()
+            .map(|name| {
+                let result = self.eval(&name);
+                let (missing, black_boxes, assumed) = self.blockers(&name);
+                let report = Report {
+                    result,
+                    missing,
+                    black_boxes,
+                    assumed,
+                };
+                (name, report)
+            })
+            .collect()
+    }
+
+    /// Append the given name to `order` after the defined names it refers to.
+    fn visit(&self, name: &str, visited: &mut BTreeSet<String>, order: &mut Vec<String>) {
+        if !visited.insert(name.to_owned()) {
+            return;
+        }
+        for dep in self.deps.get(name).into_iter().flatten() {
+            if self.ctx.contains_key(dep) {
+                self.visit(dep, visited, order);
+            }
+        }
+        order.push(name.to_owned());
+    }
+
+    /// Find the undefined names, the names defined with black boxes and those defined with
+    /// assumed outcomes that the given name refers to, directly or indirectly (the latter two
+    /// including the name itself).
+    fn blockers(&self, name: &str) -> (BTreeSet<String>, BTreeSet<String>, BTreeSet<String>) {
+        let mut missing = BTreeSet::new();
+        let mut black_boxes = BTreeSet::new();
+        let mut assumed = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![name.to_owned()];
+        while let Some(name) = stack.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            match self.get(&name) {
+                None => {
+                    missing.insert(name);
+                }
+                Some(dq) => {
+                    stack.extend(self.deps.get(&name).into_iter().flatten().cloned());
+                    if dq.any(&|dq| matches!(dq, DeltaQ::Assumed(_))) {
+                        assumed.insert(name.clone());
+                    }
+                    if dq.any(&|dq| matches!(dq, DeltaQ::BlackBox)) {
+                        black_boxes.insert(name);
+                    }
+                }
+            }
+        }
+        (missing, black_boxes, assumed)
+    }
+}
+
+/// The outcome of evaluating a name, see [`EvaluationContext::eval_all`].
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    /// The CDF of the name, or the error that prevented computing it.
+    pub result: Result<CDF, DeltaQError>,
+    /// The names referred to, directly or indirectly, that are not defined.
+    pub missing: BTreeSet<String>,
+    /// The names referred to, directly or indirectly, whose definition contains a black box;
+    /// this includes the name itself.
+    pub black_boxes: BTreeSet<String>,
+    /// The names referred to, directly or indirectly, whose definition contains an assumed
+    /// outcome, i.e. whose contribution to the result is not elaborated yet; this includes the
+    /// name itself.
+    pub assumed: BTreeSet<String>,
+}
+
+/// Prints one definition `name = expression` per line, which can be parsed back using `FromStr`.
+impl Display for EvaluationContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, dq) in self.iter() {
+            writeln!(f, "{} = {}", name, dq)?;
+        }
+        Ok(())
+    }
 }
 
 impl From<BTreeMap<String, DeltaQ>> for EvaluationContext {
@@ -90,13 +642,60 @@ impl From<BTreeMap<String, DeltaQ>> for EvaluationContext {
         Self {
             ctx: value.into_iter().map(|(k, v)| (k, (v, None))).collect(),
             deps,
+            ..Default::default()
         }
     }
 }
 
-impl Into<BTreeMap<String, DeltaQ>> for EvaluationContext {
-    fn into(self) -> BTreeMap<String, DeltaQ> {
-        self.ctx.into_iter().map(|(k, (v, _))| (k, v)).collect()
+impl From<EvaluationContext> for BTreeMap<String, DeltaQ> {
+    fn from(value: EvaluationContext) -> Self {
+        value.ctx.into_iter().map(|(k, (v, _))| (k, v)).collect()
+    }
+}
+
+/// The serialized form of an [`EvaluationContext`]: a plain map of the definitions, or an
+/// object that also holds the requirements and capacities if there are any.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum SerializedContext {
+    Full(FullContext),
+    Plain(BTreeMap<String, DeltaQ>),
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FullContext {
+    delta_q: BTreeMap<String, DeltaQ>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    requirements: BTreeMap<String, Requirement>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    capacities: BTreeMap<String, f32>,
+}
+
+impl From<SerializedContext> for EvaluationContext {
+    fn from(value: SerializedContext) -> Self {
+        match value {
+            SerializedContext::Full(full) => {
+                let mut ctx = EvaluationContext::from(full.delta_q);
+                ctx.requirements = full.requirements;
+                ctx.capacities = full.capacities;
+                ctx
+            }
+            SerializedContext::Plain(delta_q) => delta_q.into(),
+        }
+    }
+}
+
+impl From<EvaluationContext> for SerializedContext {
+    fn from(value: EvaluationContext) -> Self {
+        if value.requirements.is_empty() && value.capacities.is_empty() {
+            return Self::Plain(value.into());
+        }
+        Self::Full(FullContext {
+            requirements: value.requirements.clone(),
+            capacities: value.capacities.clone(),
+            delta_q: value.into(),
+        })
     }
 }
 
@@ -110,10 +709,23 @@ impl Into<BTreeMap<String, DeltaQ>> for EvaluationContext {
 /// - Choices are printed as `A a⇌b B`.
 /// - Universal quantifications are printed as `∀(A|B)`.
 /// - Existential quantifications are printed as `∃(A|B)`.
+/// - Repetitions are printed as `rep(x = B, n | T)`, or `rep(x = B, [w0, w1, …] | T)` for a
+///   random repetition count.
+/// - Resource usage annotations are printed as `uses(A; link=1500, cpu=0.002)`.
+/// - N-ary choices are printed as `⇌(1: A | 2: B | 3: C)`.
+/// - N-ary quantifications are printed as `∀[A | B | C]` and `∃[A | B | C]`, waiting for `k` of
+///   the outcomes as `k of [A | B | C]`.
+///
+/// The printed form can be parsed back using `FromStr`, which also accepts ASCII alternatives
+/// for the symbols: `?` for `■`, `->` for `•->-•`, `:` for `⇌`, `all` for `∀` and `any` for `∃`,
+/// as well as `choice` for `⇌` in front of n-ary choices.
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum DeltaQ {
     /// Un unelaborated and unknown DeltaQ.
     BlackBox,
+    /// An unelaborated DeltaQ with a provisional outcome, which allows evaluating a model
+    /// before all its parts are elaborated.
+    Assumed(Assumption),
     /// A named DeltaQ that can be referenced elsewhere.
     Name(String),
     /// A CDF that is used as a DeltaQ.
@@ -122,12 +734,45 @@ pub enum DeltaQ {
     Seq(Box<DeltaQ>, Box<DeltaQ>),
     /// A choice between two DeltaQs (i.e. their outcomes), with a given weight of each.
     Choice(Box<DeltaQ>, f32, Box<DeltaQ>, f32),
+    /// A choice between any number of DeltaQs, each with its weight.
+    ///
+    /// This is equivalent to nested binary choices, see [`DeltaQ::nest_choice`] and
+    /// [`DeltaQ::flatten_choice`].
+    ChoiceN(Vec<(f32, DeltaQ)>),
     /// A DeltaQ that is the result of a universal quantification over two DeltaQs,
     /// meaning that both outcomes must occur.
     ForAll(Box<DeltaQ>, Box<DeltaQ>),
     /// A DeltaQ that is the result of an existential quantification over two DeltaQs,
     /// meaning that at least one of the outcomes must occur.
     ForSome(Box<DeltaQ>, Box<DeltaQ>),
+    /// A DeltaQ that is the result of a universal quantification over any number of DeltaQs,
+    /// meaning that all outcomes must occur.
+    ForAllN(Vec<DeltaQ>),
+    /// A DeltaQ that is the result of an existential quantification over any number of
+    /// DeltaQs, meaning that at least one of the outcomes must occur.
+    ForSomeN(Vec<DeltaQ>),
+    /// A DeltaQ that occurs once the given number of the outcomes of the DeltaQs have occurred,
+    /// e.g. waiting for a quorum of votes.
+    KOfN(usize, Vec<DeltaQ>),
+    /// A template with a hole (given by name) that is applied a given number of times, starting
+    /// from a base DeltaQ: the hole is filled with the base for the first application and with
+    /// the result of the previous application thereafter.
+    Repeat(String, Box<DeltaQ>, usize, Box<DeltaQ>),
+    /// Like [`DeltaQ::Repeat`], but the number of applications is chosen randomly, where the
+    /// weights give the relative probabilities of 0, 1, 2, … applications.
+    RepeatRandom(String, Box<DeltaQ>, Vec<f32>, Box<DeltaQ>),
+    /// A DeltaQ annotated with the amounts of named resources (e.g. bytes sent over a link or
+    /// CPU time on a node) consumed by each occurrence of its outcome.
+    ///
+    /// The annotation does not change the timing, it is used for computing the load on each
+    /// resource with [`EvaluationContext::eval_load`]. Only when evaluating under load (see
+    /// [`EvaluationContext::eval_under_load`]) the time spent waiting for the resources is
+    /// added.
+    Uses(Box<DeltaQ>, BTreeMap<String, f32>),
+    /// A DeltaQ whose outcome is given up on if it has not occurred by the deadline, turning
+    /// the later mass into failure; the optional fallback is started at the deadline in that
+    /// case, e.g. for retrying.
+    Timeout(Box<DeltaQ>, f32, Option<Box<DeltaQ>>),
 }
 
 impl Display for DeltaQ {
@@ -136,6 +781,41 @@ impl Display for DeltaQ {
     }
 }
 
+/// The provisional outcome of a black box, see [`DeltaQ::Assumed`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Assumption {
+    /// The outcome is assumed to have this CDF.
+    Exact(CDF),
+    /// The outcome is assumed to lie between the pessimistic `lower` and the optimistic
+    /// `upper` CDF.
+    ///
+    /// [`DeltaQ::eval`] uses the lower bound, while [`EvaluationContext::eval_envelope`] computes
+    /// the outcome for both bounds.
+    Envelope { lower: CDF, upper: CDF },
+}
+
+impl Assumption {
+    /// Check that the pessimistic bound of an envelope lies below the optimistic one, i.e. that
+    /// its CDF is nowhere above the other.
+    pub fn validate(&self) -> Result<(), CDFError> {
+        let Assumption::Envelope { lower, upper } = self else {
+            return Ok(());
+        };
+        // rounding both bounds away from each other keeps ordered bounds ordered
+        let bin_size = lower.bin_size().min(upper.bin_size());
+        let lower = lower.resample(bin_size, Rounding::Conservative)?;
+        let upper = upper.resample(bin_size, Rounding::Optimistic)?;
+        if lower <= upper {
+            Ok(())
+        } else {
+            Err(CDFError::InvalidParameter(
+                "the pessimistic bound of an envelope must not be above the optimistic one"
+                    .to_owned(),
+            ))
+        }
+    }
+}
+
 impl DeltaQ {
     /// Create a new DeltaQ from a name, referencing a variable.
     pub fn name(name: &str) -> DeltaQ {
@@ -147,6 +827,17 @@ impl DeltaQ {
         DeltaQ::CDF(cdf)
     }
 
+    /// Create a black box with the given provisional CDF.
+    pub fn assumed(cdf: CDF) -> DeltaQ {
+        DeltaQ::Assumed(Assumption::Exact(cdf))
+    }
+
+    /// Create a black box whose outcome lies between the pessimistic `lower` and the optimistic
+    /// `upper` CDF.
+    pub fn envelope(lower: CDF, upper: CDF) -> DeltaQ {
+        DeltaQ::Assumed(Assumption::Envelope { lower, upper })
+    }
+
     /// Create a new DeltaQ from the convolution of two DeltaQs.
     pub fn seq(first: DeltaQ, second: DeltaQ) -> DeltaQ {
         DeltaQ::Seq(Box::new(first), Box::new(second))
@@ -162,6 +853,11 @@ impl DeltaQ {
         )
     }
 
+    /// Create a new DeltaQ from a choice between any number of weighted DeltaQs.
+    pub fn choice_n(branches: Vec<(f32, DeltaQ)>) -> DeltaQ {
+        DeltaQ::ChoiceN(branches)
+    }
+
     /// Create a new DeltaQ from a universal quantification over two DeltaQs.
     pub fn for_all(first: DeltaQ, second: DeltaQ) -> DeltaQ {
         DeltaQ::ForAll(Box::new(first), Box::new(second))
@@ -172,9 +868,210 @@ impl DeltaQ {
         DeltaQ::ForSome(Box::new(first), Box::new(second))
     }
 
+    /// Create a new DeltaQ from a universal quantification over any number of DeltaQs.
+    pub fn for_all_n(operands: Vec<DeltaQ>) -> DeltaQ {
+        DeltaQ::ForAllN(operands)
+    }
+
+    /// Create a new DeltaQ from an existential quantification over any number of DeltaQs.
+    pub fn for_some_n(operands: Vec<DeltaQ>) -> DeltaQ {
+        DeltaQ::ForSomeN(operands)
+    }
+
+    /// Create a new DeltaQ that occurs once `k` of the given outcomes have occurred.
+    pub fn k_of_n(k: usize, operands: Vec<DeltaQ>) -> DeltaQ {
+        DeltaQ::KOfN(k, operands)
+    }
+
+    /// Create a new DeltaQ by applying a template with the named hole `count` times to a base.
+    pub fn repeat(hole: &str, base: DeltaQ, count: usize, template: DeltaQ) -> DeltaQ {
+        DeltaQ::Repeat(hole.to_owned(), Box::new(base), count, Box::new(template))
+    }
+
+    /// Create a new DeltaQ by applying a template with the named hole a random number of times
+    /// to a base, with the given weights for 0, 1, 2, … applications.
+    pub fn repeat_random(hole: &str, base: DeltaQ, weights: Vec<f32>, template: DeltaQ) -> DeltaQ {
+        DeltaQ::RepeatRandom(hole.to_owned(), Box::new(base), weights, Box::new(template))
+    }
+
+    /// Annotate a DeltaQ with the amounts of resources consumed by each occurrence.
+    pub fn uses(delta_q: DeltaQ, usage: BTreeMap<String, f32>) -> DeltaQ {
+        DeltaQ::Uses(Box::new(delta_q), usage)
+    }
+
+    /// Create a new DeltaQ that gives up on an outcome at the deadline, continuing with the
+    /// fallback if given.
+    pub fn timeout(delta_q: DeltaQ, deadline: f32, fallback: Option<DeltaQ>) -> DeltaQ {
+        DeltaQ::Timeout(Box::new(delta_q), deadline, fallback.map(Box::new))
+    }
+
+    /// Replace all references to the given name by the given DeltaQ.
+    ///
+    /// Holes of nested repetitions shadow the name within their templates.
+    pub fn substitute(&self, name: &str, value: &DeltaQ) -> DeltaQ {
+        let sub = |dq: &DeltaQ| Box::new(dq.substitute(name, value));
+        match self {
+            DeltaQ::Name(n) if n == name => value.clone(),
+            DeltaQ::BlackBox | DeltaQ::Assumed(_) | DeltaQ::Name(_) | DeltaQ::CDF(_) => {
+                self.clone()
+            }
+            DeltaQ::Seq(first, second) => DeltaQ::Seq(sub(first), sub(second)),
+            DeltaQ::Choice(first, first_weight, second, second_weight) => {
+                DeltaQ::Choice(sub(first), *first_weight, sub(second), *second_weight)
+            }
+            DeltaQ::ForAll(first, second) => DeltaQ::ForAll(sub(first), sub(second)),
+            DeltaQ::ForSome(first, second) => DeltaQ::ForSome(sub(first), sub(second)),
+            DeltaQ::Repeat(hole, base, count, template) => {
+                let template = if hole == name {
+                    template.clone()
+                } else {
+                    sub(template)
+                };
+                DeltaQ::Repeat(hole.clone(), sub(base), *count, template)
+            }
+            DeltaQ::RepeatRandom(hole, base, weights, template) => {
+                let template = if hole == name {
+                    template.clone()
+                } else {
+                    sub(template)
+                };
+                DeltaQ::RepeatRandom(hole.clone(), sub(base), weights.clone(), template)
+            }
+            DeltaQ::Uses(delta_q, usage) => DeltaQ::Uses(sub(delta_q), usage.clone()),
+            DeltaQ::Timeout(delta_q, deadline, fallback) => {
+                DeltaQ::Timeout(sub(delta_q), *deadline, fallback.as_deref().map(sub))
+            }
+            DeltaQ::ChoiceN(branches) => DeltaQ::ChoiceN(
+                branches
+                    .iter()
+                    .map(|(weight, branch)| (*weight, *sub(branch)))
+                    .collect(),
+            ),
+            DeltaQ::ForAllN(operands) => {
+                DeltaQ::ForAllN(operands.iter().map(|o| *sub(o)).collect())
+            }
+            DeltaQ::ForSomeN(operands) => {
+                DeltaQ::ForSomeN(operands.iter().map(|o| *sub(o)).collect())
+            }
+            DeltaQ::KOfN(k, operands) => {
+                DeltaQ::KOfN(*k, operands.iter().map(|o| *sub(o)).collect())
+            }
+        }
+    }
+
+    /// Turn a choice into an n-ary choice, merging the choices directly nested in its branches
+    /// into it; other DeltaQs are returned unchanged.
+    ///
+    /// The weights of the nested branches are scaled such that all branches keep their
+    /// probability. Nested choices whose weights do not sum to a positive value have no such
+    /// probabilities, so they are kept as they are.
+    pub fn flatten_choice(&self) -> DeltaQ {
+        fn collect(delta_q: &DeltaQ, weight: f32, branches: &mut Vec<(f32, DeltaQ)>) {
+            match delta_q {
+                DeltaQ::Choice(first, first_weight, second, second_weight)
+                    if *first_weight + *second_weight > 0.0 =>
+                {
+                    let total = *first_weight + *second_weight;
+                    collect(first, weight * *first_weight / total, branches);
+                    collect(second, weight * *second_weight / total, branches);
+                }
+                DeltaQ::ChoiceN(nested) if nested.iter().map(|(w, _)| *w).sum::<f32>() > 0.0 => {
+                    let total = nested.iter().map(|(w, _)| *w).sum::<f32>();
+                    for (w, branch) in nested {
+                        collect(branch, weight * *w / total, branches);
+                    }
+                }
+                _ => branches.push((weight, delta_q.clone())),
+            }
+        }
+        let mut branches = Vec::new();
+        match self {
+            DeltaQ::Choice(first, first_weight, second, second_weight) => {
+                collect(first, *first_weight, &mut branches);
+                collect(second, *second_weight, &mut branches);
+            }
+            DeltaQ::ChoiceN(nested) => {
+                for (weight, branch) in nested {
+                    collect(branch, *weight, &mut branches);
+                }
+            }
+            _ => return self.clone(),
+        }
+        DeltaQ::ChoiceN(branches)
+    }
+
+    /// Turn an n-ary choice into nested binary choices, associating to the right; other DeltaQs
+    /// are returned unchanged.
+    ///
+    /// A choice with a single branch becomes that branch.
+    pub fn nest_choice(&self) -> DeltaQ {
+        let DeltaQ::ChoiceN(branches) = self else {
+            return self.clone();
+        };
+        let Some(((weight, last), rest)) = branches.split_last() else {
+            return self.clone();
+        };
+        let (_, nested) = rest.iter().rev().fold(
+            (*weight, last.clone()),
+            |(total, nested), (weight, branch)| {
+                let choice = DeltaQ::choice(branch.clone(), *weight, nested, total);
+                (total + *weight, choice)
+            },
+        );
+        nested
+    }
+
+    /// Get the operand with the given index, see [`Step`].
+    pub fn child(&self, index: usize) -> Option<&DeltaQ> {
+        match (self, index) {
+            (DeltaQ::BlackBox | DeltaQ::Assumed(_) | DeltaQ::Name(_) | DeltaQ::CDF(_), _) => None,
+            (
+                DeltaQ::Seq(first, _)
+                | DeltaQ::Choice(first, _, _, _)
+                | DeltaQ::ForAll(first, _)
+                | DeltaQ::ForSome(first, _)
+                | DeltaQ::Repeat(_, first, _, _)
+                | DeltaQ::RepeatRandom(_, first, _, _)
+                | DeltaQ::Uses(first, _)
+                | DeltaQ::Timeout(first, _, _),
+                0,
+            ) => Some(first),
+            (DeltaQ::Timeout(_, _, fallback), 1) => fallback.as_deref(),
+            (DeltaQ::ChoiceN(branches), index) => branches.get(index).map(|(_, branch)| branch),
+            (
+                DeltaQ::ForAllN(operands) | DeltaQ::ForSomeN(operands) | DeltaQ::KOfN(_, operands),
+                index,
+            ) => operands.get(index),
+            (
+                DeltaQ::Seq(_, second)
+                | DeltaQ::Choice(_, _, second, _)
+                | DeltaQ::ForAll(_, second)
+                | DeltaQ::ForSome(_, second)
+                | DeltaQ::Repeat(_, _, _, second)
+                | DeltaQ::RepeatRandom(_, _, _, second),
+                1,
+            ) => Some(second),
+            _ => None,
+        }
+    }
+
+    /// Get the sub-expression reached by following the given path, e.g. from a [`Location`].
+    pub fn at(&self, path: &[Step]) -> Option<&DeltaQ> {
+        path.iter().try_fold(self, |dq, step| dq.child(step.index))
+    }
+
+    /// Check whether this DeltaQ or any of its parts satisfies the given predicate, not
+    /// looking into named definitions.
+    fn any(&self, predicate: &impl Fn(&DeltaQ) -> bool) -> bool {
+        predicate(self)
+            || (0..)
+                .map_while(|index| self.child(index))
+                .any(|child| child.any(predicate))
+    }
+
     pub fn deps(&self) -> BTreeSet<String> {
         match self {
-            DeltaQ::BlackBox => BTreeSet::new(),
+            DeltaQ::BlackBox | DeltaQ::Assumed(_) => BTreeSet::new(),
             DeltaQ::Name(name) => {
                 let mut deps = BTreeSet::new();
                 deps.insert(name.clone());
@@ -201,6 +1098,28 @@ impl DeltaQ {
                 deps.extend(second.deps());
                 deps
             }
+            DeltaQ::Repeat(hole, base, _, template)
+            | DeltaQ::RepeatRandom(hole, base, _, template) => {
+                let mut deps = template.deps();
+                deps.remove(hole);
+                deps.extend(base.deps());
+                deps
+            }
+            DeltaQ::Uses(delta_q, _) => delta_q.deps(),
+            DeltaQ::Timeout(delta_q, _, fallback) => {
+                let mut deps = delta_q.deps();
+                if let Some(fallback) = fallback {
+                    deps.extend(fallback.deps());
+                }
+                deps
+            }
+            DeltaQ::ChoiceN(branches) => branches
+                .iter()
+                .flat_map(|(_, branch)| branch.deps())
+                .collect(),
+            DeltaQ::ForAllN(operands) | DeltaQ::ForSomeN(operands) | DeltaQ::KOfN(_, operands) => {
+                operands.iter().flat_map(DeltaQ::deps).collect()
+            }
         }
     }
 
@@ -209,11 +1128,17 @@ impl DeltaQ {
             DeltaQ::BlackBox => {
                 write!(f, "■")
             }
+            DeltaQ::Assumed(Assumption::Exact(cdf)) => {
+                write!(f, "■({})", cdf.literal())
+            }
+            DeltaQ::Assumed(Assumption::Envelope { lower, upper }) => {
+                write!(f, "■({}, {})", lower.literal(), upper.literal())
+            }
             DeltaQ::Name(name) => {
                 write!(f, "{}", name)
             }
             DeltaQ::CDF(cdf) => {
-                write!(f, "{:?}", cdf)
+                write!(f, "{}", cdf.literal())
             }
             DeltaQ::Seq(first, second) => {
                 if parens {
@@ -245,41 +1170,210 @@ impl DeltaQ {
             DeltaQ::ForSome(first, second) => {
                 write!(f, "∃({} | {})", first, second)
             }
+            DeltaQ::Repeat(hole, base, count, template) => {
+                write!(f, "rep({} = {}, {} | {})", hole, base, count, template)
+            }
+            DeltaQ::RepeatRandom(hole, base, weights, template) => {
+                write!(f, "rep({} = {}, [", hole, base)?;
+                for (i, weight) in weights.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", weight)?;
+                }
+                write!(f, "] | {})", template)
+            }
+            DeltaQ::Uses(delta_q, usage) => {
+                write!(f, "uses({};", delta_q)?;
+                for (i, (resource, amount)) in usage.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {}={}", resource, amount)?;
+                }
+                write!(f, ")")
+            }
+            DeltaQ::Timeout(delta_q, deadline, fallback) => {
+                write!(f, "timeout({}; {}", delta_q, deadline)?;
+                if let Some(fallback) = fallback {
+                    write!(f, " | {}", fallback)?;
+                }
+                write!(f, ")")
+            }
+            DeltaQ::ChoiceN(branches) => {
+                write!(f, "⇌(")?;
+                for (i, (weight, branch)) in branches.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}: {}", weight, branch)?;
+                }
+                write!(f, ")")
+            }
+            DeltaQ::ForAllN(operands) => {
+                write!(f, "∀")?;
+                display_list(f, operands)
+            }
+            DeltaQ::ForSomeN(operands) => {
+                write!(f, "∃")?;
+                display_list(f, operands)
+            }
+            DeltaQ::KOfN(k, operands) => {
+                write!(f, "{} of ", k)?;
+                display_list(f, operands)
+            }
+        }
+    }
+
+    /// The smallest bin size among the CDFs in this DeltaQ and the definitions it refers to.
+    fn min_bin_size(&self, ctx: &EvaluationContext, visited: &mut BTreeSet<String>) -> Option<f32> {
+        let min = |a: Option<f32>, b: Option<f32>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        match self {
+            DeltaQ::BlackBox => None,
+            DeltaQ::Assumed(Assumption::Exact(cdf)) => Some(cdf.bin_size()),
+            DeltaQ::Assumed(Assumption::Envelope { lower, upper }) => {
+                Some(lower.bin_size().min(upper.bin_size()))
+            }
+            DeltaQ::Name(name) => {
+                if !visited.insert(name.clone()) {
+                    return None;
+                }
+                ctx.get(name)?.min_bin_size(ctx, visited)
+            }
+            DeltaQ::CDF(cdf) => Some(cdf.bin_size()),
+            DeltaQ::Uses(delta_q, _) => delta_q.min_bin_size(ctx, visited),
+            DeltaQ::Timeout(delta_q, _, fallback) => min(
+                delta_q.min_bin_size(ctx, visited),
+                fallback
+                    .as_ref()
+                    .and_then(|fallback| fallback.min_bin_size(ctx, visited)),
+            ),
+            DeltaQ::ChoiceN(branches) => branches
+                .iter()
+                .map(|(_, branch)| branch.min_bin_size(ctx, visited))
+                .fold(None, min),
+            DeltaQ::ForAllN(operands) | DeltaQ::ForSomeN(operands) | DeltaQ::KOfN(_, operands) => {
+                operands
+                    .iter()
+                    .map(|operand| operand.min_bin_size(ctx, visited))
+                    .fold(None, min)
+            }
+            DeltaQ::Seq(first, second)
+            | DeltaQ::Choice(first, _, second, _)
+            | DeltaQ::ForAll(first, second)
+            | DeltaQ::ForSome(first, second)
+            | DeltaQ::Repeat(_, first, _, second)
+            | DeltaQ::RepeatRandom(_, first, _, second) => min(
+                first.min_bin_size(ctx, visited),
+                second.min_bin_size(ctx, visited),
+            ),
         }
     }
 
+    /// Evaluate two DeltaQs and bring the resulting CDFs onto a common grid.
+    ///
+    /// If the bin sizes differ, the finer one is chosen and the coarser CDF is resampled.
+    fn eval_aligned(
+        first: &DeltaQ,
+        second: &DeltaQ,
+        operator: Operator,
+        ctx: &mut EvaluationContext,
+    ) -> Result<(CDF, CDF), DeltaQError> {
+        let first_cdf = first.eval_operand(operator, 0, ctx)?;
+        let second_cdf = second.eval_operand(operator, 1, ctx)?;
+        if first_cdf.bin_size() == second_cdf.bin_size() {
+            return Ok((first_cdf, second_cdf));
+        }
+        let aligned = first_cdf.align(&second_cdf, Rounding::Conservative)?;
+        tracing::info!(
+            "Resampling CDFs with bin sizes {} and {} to bin size {}",
+            first_cdf.bin_size(),
+            second_cdf.bin_size(),
+            aligned.0.bin_size()
+        );
+        Ok(aligned)
+    }
+
+    /// Evaluate this DeltaQ as the operand with the given index of an operator, locating any
+    /// error accordingly.
+    fn eval_operand(
+        &self,
+        operator: Operator,
+        index: usize,
+        ctx: &mut EvaluationContext,
+    ) -> Result<CDF, DeltaQError> {
+        self.eval(ctx)
+            .map_err(|e| e.within(Step { operator, index }))
+    }
+
+    /// Compute the CDF of this DeltaQ, resolving names in the given context.
+    ///
+    /// Errors that occur inside the expression or inside the definition of a name carry the
+    /// [`Location`] of the failing sub-expression, see [`DeltaQError::location`].
     pub fn eval(&self, ctx: &mut EvaluationContext) -> Result<CDF, DeltaQError> {
         match self {
             DeltaQ::BlackBox => Err(DeltaQError::BlackBox),
+            DeltaQ::Assumed(Assumption::Exact(cdf)) => Ok(cdf.with_precision(ctx.precision)),
+            DeltaQ::Assumed(assumption @ Assumption::Envelope { lower, upper }) => {
+                assumption.validate()?;
+                let bound = if ctx.optimistic { upper } else { lower };
+                Ok(bound.with_precision(ctx.precision))
+            }
             DeltaQ::Name(n) => {
                 if let Some((_, Some(cdf))) = ctx.ctx.get(n) {
                     Ok(cdf.clone())
+                } else if let Some(cdf) = ctx.approximations.get(n) {
+                    Ok(cdf.clone())
+                } else if let Some(pos) = ctx.evaluating.iter().position(|x| x == n) {
+                    let mut path = ctx.evaluating[pos..].to_vec();
+                    path.push(n.to_owned());
+                    Err(DeltaQError::Cycle(path))
                 } else if let Some((dq, _)) = ctx.ctx.remove(n) {
-                    match dq.eval(ctx) {
-                        Ok(cdf) => {
-                            ctx.ctx.insert(n.to_owned(), (dq, Some(cdf.clone())));
+                    let recursive = ctx.fixpoint.is_some() && ctx.depends_on_itself(n);
+                    ctx.evaluating.push(n.to_owned());
+                    let result = if recursive {
+                        ctx.iterate(n, &dq)
+                            .map(|(cdf, residual)| (cdf, Some(residual)))
+                    } else {
+                        dq.eval(ctx).map(|cdf| (cdf, None))
+                    };
+                    ctx.evaluating.pop();
+                    // a value computed from the approximation of an enclosing fixpoint
+                    // iteration is not final
+                    let approximated = ctx.approximations.keys().any(|a| ctx.depends_on(n, a));
+                    match result {
+                        Ok((cdf, residual)) => {
+                            match residual {
+                                Some(residual) => ctx.residuals.insert(n.to_owned(), residual),
+                                None => ctx.residuals.remove(n),
+                            };
+                            let computed = (!approximated).then(|| cdf.clone());
+                            ctx.ctx.insert(n.to_owned(), (dq, computed));
                             Ok(cdf)
                         }
                         Err(e) => {
                             ctx.ctx.insert(n.to_owned(), (dq, None));
-                            Err(e)
+                            Err(e.in_definition(n))
                         }
                     }
                 } else {
                     Err(DeltaQError::NameError(n.to_owned()))
                 }
             }
-            DeltaQ::CDF(cdf) => Ok(cdf.clone()),
+            DeltaQ::CDF(cdf) => Ok(cdf.with_precision(ctx.precision)),
             DeltaQ::Seq(first, second) => {
-                let first_cdf = first.eval(ctx)?;
-                let second_cdf = second.eval(ctx)?;
+                let (first_cdf, second_cdf) =
+                    Self::eval_aligned(first, second, Operator::Seq, ctx)?;
                 first_cdf
-                    .convolve(&second_cdf)
+                    .convolve_with_max_size(&second_cdf, ctx.max_size)
                     .map_err(DeltaQError::CDFError)
             }
             DeltaQ::Choice(first, first_fraction, second, second_fraction) => {
-                let first_cdf = first.eval(ctx)?;
-                let second_cdf = second.eval(ctx)?;
+                let (first_cdf, second_cdf) =
+                    Self::eval_aligned(first, second, Operator::Choice, ctx)?;
                 first_cdf
                     .choice(
                         *first_fraction / (*first_fraction + *second_fraction),
@@ -288,21 +1382,184 @@ impl DeltaQ {
                     .map_err(DeltaQError::CDFError)
             }
             DeltaQ::ForAll(first, second) => {
-                let first_cdf = first.eval(ctx)?;
-                let second_cdf = second.eval(ctx)?;
+                let (first_cdf, second_cdf) =
+                    Self::eval_aligned(first, second, Operator::ForAll, ctx)?;
                 first_cdf
                     .for_all(&second_cdf)
                     .map_err(DeltaQError::CDFError)
             }
             DeltaQ::ForSome(first, second) => {
-                let first_cdf = first.eval(ctx)?;
-                let second_cdf = second.eval(ctx)?;
+                let (first_cdf, second_cdf) =
+                    Self::eval_aligned(first, second, Operator::ForSome, ctx)?;
                 first_cdf
                     .for_some(&second_cdf)
                     .map_err(DeltaQError::CDFError)
             }
+            DeltaQ::Repeat(hole, base, count, template) => {
+                let mut cdf = base.eval_operand(Operator::Repeat, 0, ctx)?;
+                for _ in 0..*count {
+                    cdf = template.substitute(hole, &DeltaQ::CDF(cdf)).eval_operand(
+                        Operator::Repeat,
+                        1,
+                        ctx,
+                    )?;
+                }
+                Ok(cdf)
+            }
+            DeltaQ::RepeatRandom(hole, base, weights, template) => {
+                check_weights(weights, "repetition")?;
+                let mut cdf = base.eval_operand(Operator::Repeat, 0, ctx)?;
+                // the mixture of the results for all counts so far, with its total weight
+                let mut mixture: Option<(CDF, f32)> = None;
+                for (count, &weight) in weights.iter().enumerate() {
+                    if count > 0 {
+                        cdf = template.substitute(hole, &DeltaQ::CDF(cdf)).eval_operand(
+                            Operator::Repeat,
+                            1,
+                            ctx,
+                        )?;
+                    }
+                    if weight == 0.0 {
+                        continue;
+                    }
+                    mixture = Some(match mixture {
+                        None => (cdf.clone(), weight),
+                        Some((mix, total)) => {
+                            let (mix, next) = mix.align(&cdf, Rounding::Conservative)?;
+                            (mix.choice(total / (total + weight), &next)?, total + weight)
+                        }
+                    });
+                }
+                Ok(mixture.expect("positive sum of weights").0)
+            }
+            DeltaQ::Uses(delta_q, usage) => {
+                let mut cdf = delta_q.eval_operand(Operator::Uses, 0, ctx)?;
+                for (resource, amount) in usage {
+                    let (Some(utilisation), Some(capacity)) =
+                        (ctx.utilisation.get(resource), ctx.capacities.get(resource))
+                    else {
+                        continue;
+                    };
+                    let waiting = waiting_time(
+                        *utilisation,
+                        *amount / *capacity,
+                        cdf.bin_size(),
+                        ctx.max_size,
+                    )?
+                    .with_precision(ctx.precision);
+                    cdf = cdf.convolve_with_max_size(&waiting, ctx.max_size)?;
+                }
+                Ok(cdf)
+            }
+            DeltaQ::Timeout(delta_q, deadline, None) => delta_q
+                .eval_operand(Operator::Timeout, 0, ctx)?
+                .timeout_with_max_size(*deadline, None, ctx.max_size)
+                .map_err(DeltaQError::CDFError),
+            DeltaQ::Timeout(delta_q, deadline, Some(fallback)) => {
+                let (cdf, fallback_cdf) =
+                    Self::eval_aligned(delta_q, fallback, Operator::Timeout, ctx)?;
+                cdf.timeout_with_max_size(*deadline, Some(&fallback_cdf), ctx.max_size)
+                    .map_err(DeltaQError::CDFError)
+            }
+            DeltaQ::ChoiceN(branches) => {
+                let weights = branches.iter().map(|(w, _)| *w).collect::<Vec<_>>();
+                check_weights(&weights, "choice")?;
+                let cdfs = Self::eval_all_aligned(
+                    branches.iter().map(|(_, branch)| branch),
+                    Operator::ChoiceN,
+                    ctx,
+                )?;
+                let branches = weights.into_iter().zip(cdfs).collect::<Vec<_>>();
+                Ok(CDF::mixture(&branches)?)
+            }
+            DeltaQ::ForAllN(operands) => {
+                check_operands(operands.len(), operands.len(), "quantification")?;
+                let cdfs = Self::eval_all_aligned(operands.iter(), Operator::ForAllN, ctx)?;
+                Ok(CDF::k_of_n(cdfs.len(), &cdfs)?)
+            }
+            DeltaQ::ForSomeN(operands) => {
+                check_operands(1, operands.len(), "quantification")?;
+                let cdfs = Self::eval_all_aligned(operands.iter(), Operator::ForSomeN, ctx)?;
+                Ok(CDF::k_of_n(1, &cdfs)?)
+            }
+            DeltaQ::KOfN(k, operands) => {
+                check_operands(*k, operands.len(), "k out of n")?;
+                let cdfs = Self::eval_all_aligned(operands.iter(), Operator::KOfN, ctx)?;
+                Ok(CDF::k_of_n(*k, &cdfs)?)
+            }
         }
     }
+
+    /// Evaluate the operands of an n-ary operator and bring the resulting CDFs onto the grid
+    /// with the finest bin size.
+    fn eval_all_aligned<'a>(
+        operands: impl Iterator<Item = &'a DeltaQ>,
+        operator: Operator,
+        ctx: &mut EvaluationContext,
+    ) -> Result<Vec<CDF>, DeltaQError> {
+        let cdfs = operands
+            .enumerate()
+            .map(|(index, operand)| operand.eval_operand(operator, index, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        let bin_size = cdfs.iter().map(CDF::bin_size).fold(f32::INFINITY, f32::min);
+        cdfs.into_iter()
+            .map(|cdf| {
+                if cdf.bin_size() != bin_size {
+                    tracing::info!(
+                        "Resampling CDF with bin size {} to bin size {}",
+                        cdf.bin_size(),
+                        bin_size
+                    );
+                }
+                Ok(cdf.resample(bin_size, Rounding::Conservative)?)
+            })
+            .collect()
+    }
+}
+
+/// Print the operands of an n-ary quantification as `[A | B | C]`.
+fn display_list(f: &mut fmt::Formatter<'_>, operands: &[DeltaQ]) -> fmt::Result {
+    write!(f, "[")?;
+    for (i, operand) in operands.iter().enumerate() {
+        if i > 0 {
+            write!(f, " | ")?;
+        }
+        write!(f, "{}", operand)?;
+    }
+    write!(f, "]")
+}
+
+/// Check that the given weights (of the named operator) are non-negative with a positive sum,
+/// and return the sum.
+pub(crate) fn check_weights(weights: &[f32], operator: &str) -> Result<f32, DeltaQError> {
+    let total = weights.iter().sum::<f32>();
+    if weights.iter().all(|w| *w >= 0.0 && w.is_finite()) && total > 0.0 {
+        Ok(total)
+    } else {
+        Err(CDFError::InvalidParameter(format!(
+            "{} weights must be non-negative with a positive sum",
+            operator
+        ))
+        .into())
+    }
+}
+
+/// Check that an n-ary quantification (of the named operator) has operands and that the
+/// number `k` of outcomes to wait for is between one and their count.
+pub(crate) fn check_operands(k: usize, count: usize, operator: &str) -> Result<(), DeltaQError> {
+    if count == 0 {
+        return Err(
+            CDFError::InvalidParameter(format!("{} needs at least one operand", operator)).into(),
+        );
+    }
+    if !(1..=count).contains(&k) {
+        return Err(CDFError::InvalidParameter(format!(
+            "k must be between 1 and the number of outcomes {}, found {}",
+            count, k
+        ))
+        .into());
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -320,7 +1577,7 @@ mod tests {
     fn test_display_cdf() {
         let cdf = CDF::new(&[0.0, 0.2, 0.9], 1.0).unwrap();
         let dq = DeltaQ::cdf(cdf.clone());
-        assert_eq!(dq.to_string(), format!("{:?}", cdf));
+        assert_eq!(dq.to_string(), cdf.literal().to_string());
     }
 
     #[test]
@@ -355,6 +1612,36 @@ mod tests {
         assert_eq!(for_some.to_string(), "∃(A | B)");
     }
 
+    #[test]
+    fn test_display_repeat() {
+        let template = DeltaQ::seq(DeltaQ::name("A"), DeltaQ::name("x"));
+        let repeat = DeltaQ::repeat("x", DeltaQ::name("B"), 3, template.clone());
+        assert_eq!(repeat.to_string(), "rep(x = B, 3 | A •->-• x)");
+        let repeat = DeltaQ::repeat_random("x", DeltaQ::name("B"), vec![0.5, 0.0, 2.0], template);
+        assert_eq!(repeat.to_string(), "rep(x = B, [0.5, 0, 2] | A •->-• x)");
+        assert_eq!(
+            repeat.deps(),
+            ["A".to_owned(), "B".to_owned()].into_iter().collect()
+        );
+        let json = serde_json::to_string(&repeat).unwrap();
+        assert_eq!(serde_json::from_str::<DeltaQ>(&json).unwrap(), repeat);
+    }
+
+    #[test]
+    fn test_substitute() {
+        let dq = DeltaQ::seq(
+            DeltaQ::name("x"),
+            DeltaQ::repeat("x", DeltaQ::name("x"), 2, DeltaQ::name("x")),
+        );
+        assert_eq!(
+            dq.substitute("x", &DeltaQ::name("y")),
+            DeltaQ::seq(
+                DeltaQ::name("y"),
+                DeltaQ::repeat("x", DeltaQ::name("y"), 2, DeltaQ::name("x")),
+            )
+        );
+    }
+
     #[test]
     fn test_display_nested_seq() {
         let dq1 = DeltaQ::name("A");
@@ -435,8 +1722,263 @@ mod tests {
                     100.0,
                 ),
         };
-        let result = DeltaQ::name("model5").eval(&mut ctx.into()).unwrap();
+        let mut ctx: EvaluationContext = ctx.into();
+        let result = DeltaQ::name("model5").eval(&mut ctx).unwrap();
         assert_eq!(result.to_string(), "CDF[(0.0200, 0.0033), (0.0400, 0.0044), (0.0600, 0.0048), (0.0800, 0.0049), (0.1000, 0.0089), (0.1400, 0.0122), (0.1600, 0.0144), (0.1800, 0.0155), (0.2000, 0.0159), (0.2200, 0.0357), (0.2800, 0.0368), (0.3000, 0.0379), (0.3200, 0.0386), (0.3400, 0.0782), (0.4200, 0.0786), (0.4400, 0.0791), (0.4600, 0.1187), (0.5300, 0.1220), (0.5500, 0.1241), (0.5600, 0.1242), (0.5700, 0.1253), (0.5800, 0.1451), (0.5900, 0.1456), (0.6100, 0.1654), (0.6700, 0.1676), (0.6900, 0.1697), (0.7000, 0.1737), (0.7100, 0.1751), (0.7300, 0.2542), (0.8100, 0.2553), (0.8300, 0.2567), (0.8500, 0.3753), (0.9500, 0.3758), (0.9700, 0.4549), (1.0600, 0.4560), (1.0800, 0.4570), (1.0900, 0.4768), (1.1000, 0.4775), (1.1200, 0.5171), (1.2000, 0.5181), (1.2200, 0.5195), (1.2400, 0.6381), (1.3400, 0.6388), (1.3600, 0.7575), (1.4800, 0.7970), (1.5900, 0.7974), (1.6100, 0.7978), (1.6300, 0.8374), (1.7300, 0.8378), (1.7500, 0.9169), (1.8700, 0.9564), (2.1200, 0.9565), (2.1400, 0.9763), (2.2600, 0.9960), (2.6500, 1.0000)]");
+
+        ctx.set_precision(Precision::Float64);
+        let precise = DeltaQ::name("model5").eval(&mut ctx).unwrap();
+        assert_eq!(precise.precision(), Precision::Float64);
+        // the result agrees with the fixed point computation up to rounding in the last digit
+        assert_eq!(precise.to_string(), "CDF[(0.0200, 0.0033), (0.0400, 0.0044), (0.0600, 0.0047), (0.0800, 0.0049), (0.1000, 0.0088), (0.1400, 0.0121), (0.1600, 0.0143), (0.1800, 0.0154), (0.2000, 0.0159), (0.2200, 0.0356), (0.2800, 0.0367), (0.3000, 0.0378), (0.3200, 0.0385), (0.3400, 0.0781), (0.4200, 0.0784), (0.4400, 0.0789), (0.4600, 0.1184), (0.5300, 0.1217), (0.5500, 0.1239), (0.5600, 0.1240), (0.5700, 0.1251), (0.5800, 0.1449), (0.5900, 0.1454), (0.6100, 0.1651), (0.6700, 0.1673), (0.6900, 0.1695), (0.7000, 0.1734), (0.7100, 0.1749), (0.7300, 0.2540), (0.8100, 0.2550), (0.8300, 0.2565), (0.8500, 0.3751), (0.9500, 0.3756), (0.9700, 0.4547), (1.0600, 0.4558), (1.0800, 0.4568), (1.0900, 0.4766), (1.1000, 0.4773), (1.1200, 0.5169), (1.2000, 0.5179), (1.2200, 0.5194), (1.2400, 0.6380), (1.3400, 0.6387), (1.3600, 0.7574), (1.4800, 0.7969), (1.5900, 0.7973), (1.6100, 0.7977), (1.6300, 0.8373), (1.7300, 0.8378), (1.7500, 0.9168), (1.8700, 0.9564), (2.1200, 0.9565), (2.1400, 0.9763), (2.2600, 0.9960), (2.6500, 1.0000)]");
+    }
+
+    #[test]
+    fn test_repeat_scenario_from_paper() {
+        let template = DeltaQ::choice(
+            DeltaQ::name("single"),
+            1.0,
+            DeltaQ::seq(DeltaQ::name("single"), DeltaQ::name("hole")),
+            100.0,
+        );
+        let ctx = btreemap! {
+            "single".to_owned() =>
+                DeltaQ::cdf(CDF::step(
+                    &[(0.024, 1.0 / 3.0), (0.143, 2.0 / 3.0), (0.531, 1.0)],
+                    0.01,
+                    300,
+                )
+                .unwrap()),
+            "model2".to_owned() => template.substitute("hole", &DeltaQ::name("single")),
+            "model3".to_owned() => template.substitute("hole", &DeltaQ::name("model2")),
+            "model5".to_owned() => DeltaQ::repeat("hole", DeltaQ::name("single"), 4, template.clone()),
+            "random".to_owned() =>
+                DeltaQ::repeat_random("hole", DeltaQ::name("single"), vec![0.0, 1.0, 3.0], template.clone()),
+            "explicit".to_owned() => DeltaQ::choice(DeltaQ::name("model2"), 1.0, DeltaQ::name("model3"), 3.0),
+        };
+        let mut ctx: EvaluationContext = ctx.into();
+        let result = ctx.eval("model5").unwrap();
+        assert_eq!(result.to_string(), "CDF[(0.0200, 0.0033), (0.0400, 0.0044), (0.0600, 0.0048), (0.0800, 0.0049), (0.1000, 0.0089), (0.1400, 0.0122), (0.1600, 0.0144), (0.1800, 0.0155), (0.2000, 0.0159), (0.2200, 0.0357), (0.2800, 0.0368), (0.3000, 0.0379), (0.3200, 0.0386), (0.3400, 0.0782), (0.4200, 0.0786), (0.4400, 0.0791), (0.4600, 0.1187), (0.5300, 0.1220), (0.5500, 0.1241), (0.5600, 0.1242), (0.5700, 0.1253), (0.5800, 0.1451), (0.5900, 0.1456), (0.6100, 0.1654), (0.6700, 0.1676), (0.6900, 0.1697), (0.7000, 0.1737), (0.7100, 0.1751), (0.7300, 0.2542), (0.8100, 0.2553), (0.8300, 0.2567), (0.8500, 0.3753), (0.9500, 0.3758), (0.9700, 0.4549), (1.0600, 0.4560), (1.0800, 0.4570), (1.0900, 0.4768), (1.1000, 0.4775), (1.1200, 0.5171), (1.2000, 0.5181), (1.2200, 0.5195), (1.2400, 0.6381), (1.3400, 0.6388), (1.3600, 0.7575), (1.4800, 0.7970), (1.5900, 0.7974), (1.6100, 0.7978), (1.6300, 0.8374), (1.7300, 0.8378), (1.7500, 0.9169), (1.8700, 0.9564), (2.1200, 0.9565), (2.1400, 0.9763), (2.2600, 0.9960), (2.6500, 1.0000)]");
+        assert_eq!(
+            ctx.eval("random").unwrap().to_string(),
+            ctx.eval("explicit").unwrap().to_string()
+        );
+        assert_eq!(
+            ctx.eval("model5").unwrap(),
+            DeltaQ::repeat("hole", DeltaQ::name("model2"), 3, template.clone())
+                .eval(&mut ctx)
+                .unwrap()
+        );
+        assert_eq!(
+            DeltaQ::repeat("hole", DeltaQ::name("single"), 0, template.clone()).eval(&mut ctx),
+            ctx.eval("single")
+        );
+        assert_eq!(
+            DeltaQ::repeat_random("hole", DeltaQ::name("single"), vec![0.0], template)
+                .eval(&mut ctx),
+            Err(DeltaQError::CDFError(CDFError::InvalidParameter(
+                "repetition weights must be non-negative with a positive sum".to_owned()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_different_widths() {
+        let mut ctx = EvaluationContext::default();
+        ctx.put(
+            "short".to_owned(),
+            DeltaQ::cdf(CDF::step(&[(0.2, 1.0)], 0.1, 5).unwrap()),
+        )
+        .unwrap();
+        ctx.put(
+            "long".to_owned(),
+            DeltaQ::cdf(CDF::step(&[(0.1, 0.5), (2.0, 1.0)], 0.1, 30).unwrap()),
+        )
+        .unwrap();
+        ctx.put(
+            "seq".to_owned(),
+            DeltaQ::seq(DeltaQ::name("short"), DeltaQ::name("long")),
+        )
+        .unwrap();
+        ctx.put(
+            "all".to_owned(),
+            DeltaQ::for_all(DeltaQ::name("short"), DeltaQ::name("long")),
+        )
+        .unwrap();
+        let seq = ctx.eval("seq").unwrap();
+        assert_eq!(seq.width(), 3.5);
+        assert_eq!(seq.to_string(), "CDF[(0.3000, 0.5000), (2.2000, 1.0000)]");
+        let all = ctx.eval("all").unwrap();
+        assert_eq!(all.width(), 3.0);
+        assert_eq!(all.to_string(), "CDF[(0.2000, 0.5000), (2.0000, 1.0000)]");
+
+        ctx.set_max_size(20);
+        let seq = ctx.eval("seq").unwrap();
+        assert_eq!(seq.width(), 2.0);
+        assert_eq!(seq.to_string(), "CDF[(0.3000, 0.5000)]");
+        assert_eq!(format!("{:.4}", seq.truncated_mass()), "0.5000");
+    }
+
+    #[test]
+    fn test_choice_n() {
+        let mut ctx: EvaluationContext = "
+            fast = CDF[(0.1, 1); bin_size=0.1, bins=4]
+            slow = CDF[(0.2, 1); bin_size=0.1, bins=4]
+            lossy = CDF[(0.3, 0.5); bin_size=0.05, bins=8]
+            flat = choice(1: fast | 2: slow | 1: lossy)
+            nested = fast 1:3 (slow 2:1 lossy)
+        "
+        .parse()
+        .unwrap();
+        let flat = ctx.eval("flat").unwrap();
+        let nested = ctx.eval("nested").unwrap();
+        assert_eq!(flat.bin_size(), 0.05);
+        assert!(flat.kolmogorov_smirnov(&nested).unwrap() < 1e-4);
+        assert!((flat.completion_probability() - 0.875).abs() < 1e-4);
+
+        // conversions preserve the probability of each branch
+        let nested_dq = ctx.get("nested").unwrap().clone();
+        let flattened = nested_dq.flatten_choice();
+        assert_eq!(flattened.to_string(), "⇌(1: fast | 2: slow | 1: lossy)");
+        assert_eq!(
+            ctx.get("flat").unwrap().nest_choice().to_string(),
+            "fast 1⇌3 (slow 2⇌1 lossy)"
+        );
+        assert_eq!(flattened.nest_choice().flatten_choice(), flattened);
+        assert_eq!(DeltaQ::name("A").flatten_choice(), DeltaQ::name("A"));
+        // nested choices without positive total weight are not merged
+        let zero: DeltaQ = "A 1:1 (B 0:0 C)".parse().unwrap();
+        assert_eq!(zero.flatten_choice().to_string(), "⇌(1: A | 1: B 0⇌0 C)");
+        assert_eq!(
+            DeltaQ::choice_n(vec![(2.0, DeltaQ::name("A"))]).nest_choice(),
+            DeltaQ::name("A")
+        );
+
+        ctx.put(
+            "broken".to_owned(),
+            "choice(0: fast | 0: slow)".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            ctx.eval("broken").unwrap_err().to_string(),
+            "CDF error: Invalid parameter: choice weights must be non-negative with a positive sum \
+             at `broken`"
+        );
+        ctx.put(
+            "missing".to_owned(),
+            "choice(1: fast | 1: gone)".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            ctx.eval("missing").unwrap_err().to_string(),
+            "Name error: gone at branch 1 of ChoiceN in `missing`"
+        );
+    }
+
+    #[test]
+    fn test_quantification_n() {
+        let mut ctx: EvaluationContext = "
+            fast = CDF[(0.1, 1); bin_size=0.1, bins=4]
+            slow = CDF[(0.2, 1); bin_size=0.1, bins=4]
+            lossy = CDF[(0.1, 0.5); bin_size=0.05, bins=8]
+            all = all[fast | slow | lossy]
+            all_nested = all(fast | all(slow | lossy))
+            any = any[fast | slow | lossy]
+            any_nested = any(fast | any(slow | lossy))
+            two = 2 of [fast | slow | lossy]
+            identical = 2 of [lossy | lossy | lossy]
+            too_many = 4 of [fast | slow | lossy]
+        "
+        .parse()
+        .unwrap();
+        for (flat, nested) in [("all", "all_nested"), ("any", "any_nested")] {
+            let flat = ctx.eval(flat).unwrap();
+            let nested = ctx.eval(nested).unwrap();
+            assert!(flat.kolmogorov_smirnov(&nested).unwrap() < 1e-4);
+        }
+        assert!((ctx.eval("all").unwrap().completion_probability() - 0.5).abs() < 1e-4);
+
+        // the second outcome occurs once `fast` and `lossy` or `slow` have occurred
+        assert_eq!(
+            ctx.eval("two").unwrap().to_string(),
+            "CDF[(0.1000, 0.5000), (0.2000, 1.0000)]"
+        );
+        // binomial: 3 p² (1 - p) + p³ with p = 0.5
+        let identical = ctx.eval("identical").unwrap();
+        assert_eq!(identical.to_string(), "CDF[(0.1000, 0.5000)]");
+
+        assert_eq!(
+            ctx.eval("too_many").unwrap_err().to_string(),
+            "CDF error: Invalid parameter: k must be between 1 and the number of outcomes 3, \
+             found 4 at `too_many`"
+        );
+        assert_eq!(
+            DeltaQ::for_all_n(vec![])
+                .eval(&mut ctx)
+                .unwrap_err()
+                .to_string(),
+            "CDF error: Invalid parameter: quantification needs at least one operand"
+        );
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut ctx: EvaluationContext = "
+            req = CDF[(0.1, 0.5), (0.3, 0.9); bin_size=0.1, bins=5]
+            cut = timeout(req; 0.2)
+            retry = timeout(req; 0.2 | req)
+            late = timeout(req; 1)
+            broken = timeout(req; 0.2 | req -> ?)
+        "
+        .parse()
+        .unwrap();
+        let cut = ctx.eval("cut").unwrap();
+        assert_eq!(cut.to_string(), "CDF[(0.1000, 0.5000)]");
+        assert!((cut.failure_probability() - 0.5).abs() < 1e-4);
+
+        // the retry is started at the deadline for the half that has not occurred by then
+        let retry = ctx.eval("retry").unwrap();
+        assert_eq!(
+            retry.to_string(),
+            "CDF[(0.1000, 0.5000), (0.3000, 0.7500), (0.5000, 0.9500)]"
+        );
+        assert!((retry.completion_probability() - 0.95).abs() < 1e-4);
+
+        assert_eq!(ctx.eval("late"), ctx.eval("req"));
+
+        // a deadline far beyond the horizon does not widen the result beyond the maximal size
+        ctx.put(
+            "far".to_owned(),
+            "timeout(req; 1000 | req)".parse().unwrap(),
+        )
+        .unwrap();
+        ctx.set_max_size(100);
+        let far = ctx.eval("far").unwrap();
+        assert_eq!(far.width(), 10.0);
+        assert!((far.probability_at(10.0) - 0.9).abs() < 1e-4);
+        assert!((far.truncated_mass() - 0.09).abs() < 1e-4);
+        assert_eq!(
+            ctx.eval("broken").unwrap_err().to_string(),
+            "Black box encountered at right of Seq, fallback of Timeout in `broken`"
+        );
+    }
+
+    #[test]
+    fn test_different_bin_sizes() {
+        let ctx = btreemap! {
+            "link".to_owned() => DeltaQ::cdf(CDF::step(&[(0.0025, 0.5), (0.0055, 1.0)], 0.001, 10).unwrap()),
+            "proc".to_owned() => DeltaQ::cdf(CDF::step(&[(0.00035, 1.0)], 0.0001, 10).unwrap()),
+            "seq".to_owned() => DeltaQ::seq(DeltaQ::name("link"), DeltaQ::name("proc")),
+            "all".to_owned() => DeltaQ::for_all(DeltaQ::name("link"), DeltaQ::name("proc")),
+        };
+        let mut ctx: EvaluationContext = ctx.into();
+        let seq = ctx.eval("seq").unwrap();
+        assert_eq!(seq.bin_size(), 0.0001);
+        assert_eq!(seq.to_string(), "CDF[(0.0023, 0.5000), (0.0053, 1.0000)]");
+        let all = ctx.eval("all").unwrap();
+        assert_eq!(all.bin_size(), 0.0001);
+        assert_eq!(all.width(), 0.01);
+        assert_eq!(all.to_string(), "CDF[(0.0020, 0.5000), (0.0050, 1.0000)]");
     }
 
     #[test]
@@ -452,6 +1994,311 @@ mod tests {
             "base".to_owned() => DeltaQ::cdf(CDF::new(&[0.0, 0.5, 1.0], 1.0).unwrap()),
         };
         let result = DeltaQ::name("recursive").eval(&mut ctx.into()).unwrap_err();
-        assert_eq!(result, DeltaQError::NameError("recursive".to_owned()));
+        assert_eq!(
+            result.cause(),
+            &DeltaQError::Cycle(vec!["recursive".to_owned(), "recursive".to_owned()])
+        );
+        assert_eq!(
+            result.to_string(),
+            "Cycle: recursive -> recursive at right of Seq, right of Choice in `recursive`"
+        );
+    }
+
+    #[test]
+    fn test_fixpoint() {
+        let mut ctx = EvaluationContext::default();
+        ctx.set_fixpoint(Some(Fixpoint {
+            max_depth: 5,
+            tolerance: 0.0,
+        }));
+        // retry until success: each attempt takes one second and succeeds with probability 1/2
+        let retry: DeltaQ = "base 1:1 (base -> retry)".parse().unwrap();
+        ctx.put("retry".to_owned(), retry).unwrap();
+        ctx.put(
+            "base".to_owned(),
+            DeltaQ::cdf(CDF::step(&[(1.0, 1.0)], 1.0, 2).unwrap()),
+        )
+        .unwrap();
+        ctx.put("twice".to_owned(), "retry -> retry".parse().unwrap())
+            .unwrap();
+
+        let cdf = ctx.eval("retry").unwrap();
+        assert_eq!(
+            cdf.to_string(),
+            "CDF[(1.0000, 0.5000), (2.0000, 0.7500), (3.0000, 0.8750), (4.0000, 0.9375), (5.0000, 0.9688)]"
+        );
+        assert_eq!(format!("{:.4}", ctx.residual("retry").unwrap()), "0.0312");
+        assert_eq!(ctx.residual("base"), None);
+        let twice = ctx.eval("twice").unwrap();
+        assert_eq!(format!("{:.4}", twice.probability_at(2.0)), "0.2500");
+        assert_eq!(ctx.residual("twice"), None);
+
+        // a tolerance stops the iteration once the CDF changes little
+        ctx.set_fixpoint(Some(Fixpoint {
+            max_depth: 100,
+            tolerance: 0.01,
+        }));
+        assert_eq!(ctx.residual("retry"), None);
+        let cdf = ctx.eval("retry").unwrap();
+        assert_eq!(format!("{:.4}", cdf.completion_probability()), "0.9922");
+        assert_eq!(format!("{:.4}", ctx.residual("retry").unwrap()), "0.0078");
+
+        // mutual recursion
+        ctx.put(
+            "ping".to_owned(),
+            "base 1:1 (base -> pong)".parse().unwrap(),
+        )
+        .unwrap();
+        ctx.put("pong".to_owned(), "base -> ping".parse().unwrap())
+            .unwrap();
+        let ping = ctx.eval("ping").unwrap();
+        assert_eq!(format!("{:.4}", ping.probability_at(3.0)), "0.7500");
+
+        ctx.set_fixpoint(None);
+        assert_eq!(
+            ctx.eval("retry").unwrap_err().cause(),
+            &DeltaQError::Cycle(vec!["retry".to_owned(), "retry".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_eval_all() {
+        let mut ctx: EvaluationContext = "
+            link = CDF[(0.1, 1); bin_size=0.1, bins=3]
+            good = link -> link
+            open = link -> ?
+            dangling = link 1:1 (lost -> gone)
+            top = all(good | open -> dangling)
+        "
+        .parse()
+        .unwrap();
+        let reports = ctx.eval_all();
+        assert_eq!(reports.len(), 5);
+
+        let good = &reports["good"];
+        assert_eq!(good.result, ctx.eval("good"));
+        assert!(good.missing.is_empty() && good.black_boxes.is_empty());
+
+        let open = &reports["open"];
+        assert_eq!(
+            open.result.as_ref().unwrap_err().to_string(),
+            "Black box encountered at right of Seq in `open`"
+        );
+        assert_eq!(open.black_boxes, BTreeSet::from(["open".to_owned()]));
+
+        let dangling = &reports["dangling"];
+        assert_eq!(
+            dangling.result.as_ref().unwrap_err().to_string(),
+            "Name error: lost at left of Seq, right of Choice in `dangling`"
+        );
+        assert_eq!(
+            dangling.missing,
+            BTreeSet::from(["gone".to_owned(), "lost".to_owned()])
+        );
+
+        let top = &reports["top"];
+        assert_eq!(
+            top.result.as_ref().unwrap_err(),
+            open.result.as_ref().unwrap_err()
+        );
+        assert_eq!(top.missing, dangling.missing);
+        assert_eq!(top.black_boxes, open.black_boxes);
+    }
+
+    #[test]
+    fn test_assumed() {
+        let mut ctx: EvaluationContext = "
+            link = CDF[(0.1, 1); bin_size=0.1, bins=4]
+            server = ?(CDF[(0.1, 0.5), (0.2, 1); bin_size=0.1, bins=4])
+            backend = ?(CDF[(0.2, 1); bin_size=0.1, bins=4], CDF[(0.1, 1); bin_size=0.1, bins=4])
+            model = link -> server
+            full = model -> backend
+        "
+        .parse()
+        .unwrap();
+        assert_eq!(
+            ctx.eval("model").unwrap(),
+            CDF::step(&[(0.2, 0.5), (0.3, 1.0)], 0.1, 4).unwrap()
+        );
+
+        let (lower, upper) = ctx.eval_envelope("full").unwrap();
+        assert_eq!(lower, ctx.eval("full").unwrap());
+        assert_eq!(lower.to_string(), "CDF[(0.4000, 0.5000), (0.5000, 1.0000)]");
+        assert_eq!(upper.to_string(), "CDF[(0.3000, 0.5000), (0.4000, 1.0000)]");
+        let (lower, upper) = ctx.eval_envelope("model").unwrap();
+        assert_eq!(lower, upper);
+
+        let reports = ctx.eval_all();
+        assert_eq!(
+            reports["full"].assumed,
+            BTreeSet::from(["backend".to_owned(), "server".to_owned()])
+        );
+        assert!(reports["link"].assumed.is_empty());
+        assert!(reports["full"].black_boxes.is_empty());
+
+        // swapped bounds are rejected
+        let swapped: DeltaQ =
+            "?(CDF[(0.1, 1); bin_size=0.1, bins=4], CDF[(0.2, 1); bin_size=0.1, bins=4])"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            swapped.eval(&mut ctx).unwrap_err().to_string(),
+            "CDF error: Invalid parameter: the pessimistic bound of an envelope must not be \
+             above the optimistic one"
+        );
+        let finer: DeltaQ =
+            "?(CDF[(0.2, 1); bin_size=0.1, bins=4], CDF[(0.15, 1); bin_size=0.05, bins=8])"
+                .parse()
+                .unwrap();
+        assert!(finer.eval(&mut ctx).is_ok());
+    }
+
+    #[test]
+    fn test_error_location() {
+        let mut ctx: EvaluationContext = "
+            link = CDF[(0.1, 1); bin_size=0.1, bins=3]
+            model = link 1:2 all(link | link -> rep(x = link, [0, 1] | x -> hop))
+        "
+        .parse()
+        .unwrap();
+        let err = ctx.eval("model").unwrap_err();
+        assert_eq!(err.cause(), &DeltaQError::NameError("hop".to_owned()));
+        assert_eq!(
+            err.to_string(),
+            "Name error: hop at right of Seq, template of Repeat, right of Seq, right of ForAll, \
+             right of Choice in `model`"
+        );
+        let location = err.location().unwrap();
+        let model = ctx.get("model").unwrap();
+        assert_eq!(model.at(&location.path), Some(&DeltaQ::name("hop")));
+
+        // errors in anonymous expressions have no name, errors at the root no location
+        let err = DeltaQ::seq(DeltaQ::name("link"), DeltaQ::BlackBox)
+            .eval(&mut ctx)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Black box encountered at right of Seq");
+        assert_eq!(DeltaQ::BlackBox.eval(&mut ctx), Err(DeltaQError::BlackBox));
+    }
+
+    #[test]
+    fn test_cycle_detection() {
+        let mut ctx = EvaluationContext::default();
+        ctx.put("a".to_owned(), DeltaQ::name("b")).unwrap();
+        ctx.put(
+            "b".to_owned(),
+            DeltaQ::seq(DeltaQ::name("c"), DeltaQ::name("d")),
+        )
+        .unwrap();
+        ctx.put("d".to_owned(), DeltaQ::BlackBox).unwrap();
+        let err = ctx
+            .put(
+                "c".to_owned(),
+                DeltaQ::for_all(DeltaQ::BlackBox, DeltaQ::name("a")),
+            )
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Cycle: c -> a -> b -> c");
+        assert_eq!(ctx.get("c"), None);
+        assert_eq!(
+            ctx.put("d".to_owned(), DeltaQ::name("d")),
+            Err(DeltaQError::Cycle(vec!["d".to_owned(), "d".to_owned()]))
+        );
+        assert_eq!(ctx.get("d"), Some(&DeltaQ::BlackBox));
+
+        // cycles can still enter through the conversion from a map, they are found when evaluating
+        let mut ctx: EvaluationContext = btreemap! {
+            "a".to_owned() => DeltaQ::name("b"),
+            "b".to_owned() => DeltaQ::seq(DeltaQ::name("a"), DeltaQ::BlackBox),
+        }
+        .into();
+        assert_eq!(
+            ctx.eval("a").unwrap_err().to_string(),
+            "Cycle: a -> b -> a at left of Seq in `b`"
+        );
+        assert_eq!(
+            ctx.eval("b").unwrap_err().to_string(),
+            "Cycle: b -> a -> b at `a`"
+        );
+    }
+
+    #[test]
+    fn test_requirements() {
+        let ctx = btreemap! {
+            "link".to_owned() => DeltaQ::cdf(CDF::step(&[(0.1, 0.5), (0.2, 1.0)], 0.01, 30).unwrap()),
+            "two".to_owned() => DeltaQ::seq(DeltaQ::name("link"), DeltaQ::name("link")),
+        };
+        let mut ctx: EvaluationContext = ctx.into();
+        ctx.set_requirement(
+            "link".to_owned(),
+            Requirement::new(&[(0.1, 0.5), (0.2, 0.99)]).unwrap(),
+        );
+        ctx.set_requirement(
+            "two".to_owned(),
+            Requirement::new(&[(0.2, 0.5), (0.3, 0.9)]).unwrap(),
+        );
+        ctx.set_requirement(
+            "missing".to_owned(),
+            Requirement::new(&[(1.0, 1.0)]).unwrap(),
+        );
+        assert_eq!(ctx.check("link"), Ok(Some(Verdict::Satisfied)));
+        let tightest = ctx.slack("link").unwrap().unwrap().tightest.unwrap();
+        assert_eq!((tightest.time, tightest.probability), (0.1, 0.5));
+        assert_eq!(format!("{:.4}", tightest.time_slack.unwrap()), "0.0000");
+        let tightest = ctx.slack("two").unwrap().unwrap().tightest.unwrap();
+        assert_eq!(format!("{:.4}", tightest.time_slack.unwrap()), "-0.1000");
+
+        let verdicts = ctx.check_all();
+        assert_eq!(verdicts.len(), 3);
+        assert_eq!(verdicts["link"], Ok(Verdict::Satisfied));
+        let Ok(Verdict::Violated(violations)) = &verdicts["two"] else {
+            panic!("expected violation: {:?}", verdicts["two"]);
+        };
+        assert_eq!(violations.len(), 2);
+        assert_eq!(format!("{:.4}", violations[0].actual), "0.2500");
+        assert_eq!(format!("{:.4}", violations[1].late_by.unwrap()), "0.1000");
+        assert_eq!(
+            verdicts["missing"],
+            Err(DeltaQError::NameError("missing".to_owned()))
+        );
+
+        // requirements are removed together with their name
+        ctx.remove("two");
+        assert_eq!(ctx.requirement("two"), None);
+        assert_eq!(
+            ctx.slack("two"),
+            Err(DeltaQError::NameError("two".to_owned()))
+        );
+        assert_eq!(ctx.check("link"), Ok(Some(Verdict::Satisfied)));
+        ctx.remove_requirement("link");
+        assert_eq!(ctx.slack("link"), Ok(None));
+    }
+
+    #[test]
+    fn test_serde_context() {
+        let ctx = btreemap! {
+            "link".to_owned() => DeltaQ::uses(DeltaQ::cdf(CDF::new(&[0.5, 1.0], 0.1).unwrap()), btreemap! { "cpu".to_owned() => 1.0 }),
+        };
+        let mut ctx: EvaluationContext = ctx.into();
+        let requirement = Requirement::new(&[(0.1, 0.5)]).unwrap();
+        ctx.set_requirement("link".to_owned(), requirement.clone());
+        ctx.set_capacity("cpu".to_owned(), 10.0);
+
+        let json = serde_json::to_string(&ctx).unwrap();
+        let ctx2 = serde_json::from_str::<EvaluationContext>(&json).unwrap();
+        assert_eq!(ctx2, ctx);
+        assert_eq!(ctx2.requirement("link"), Some(&requirement));
+        assert_eq!(ctx2.capacity("cpu"), Some(10.0));
+
+        // requirements and capacities are optional
+        let ctx3 =
+            serde_json::from_str::<EvaluationContext>(r#"{"delta_q":{"a":"BlackBox"}}"#).unwrap();
+        assert_eq!(ctx3.get("a"), Some(&DeltaQ::BlackBox));
+        assert_eq!(ctx3.requirement("a"), None);
+
+        // without requirements and capacities the context is a plain map of definitions
+        let json = r#"{"a":"BlackBox","b":{"Name":"a"}}"#;
+        let ctx4 = serde_json::from_str::<EvaluationContext>(json).unwrap();
+        assert_eq!(ctx4.get("a"), Some(&DeltaQ::BlackBox));
+        assert_eq!(ctx4.get("b"), Some(&DeltaQ::name("a")));
+        assert_eq!(serde_json::to_string(&ctx4).unwrap(), json);
     }
 }
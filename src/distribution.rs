@@ -0,0 +1,477 @@
+use crate::{CDFError, CDF};
+use std::f64::consts::{PI, SQRT_2};
+
+/// A parametric probability distribution of completion times.
+///
+/// Distributions are turned into [`CDF`]s by discretising them onto a grid of bins using
+/// [`Distribution::to_cdf`]. Bin `i` then holds the probability of completing by the start of
+/// that bin (i.e. by time `i * bin_size`), which matches the convention of [`CDF::step`] and
+/// [`CDF::probability_at`]; mass within a bin is thus rounded up to the next bin boundary.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Distribution {
+    /// Completion after exactly the given delay.
+    Deterministic { delay: f32 },
+    /// Completion uniformly distributed between `min` and `max`.
+    Uniform { min: f32, max: f32 },
+    /// Exponentially distributed completion with the given rate (i.e. mean `1 / rate`).
+    Exponential { rate: f32 },
+    /// Exponentially distributed completion after a fixed delay `shift`.
+    ShiftedExponential { shift: f32, rate: f32 },
+    /// Normally distributed completion, truncated at zero and renormalised.
+    Normal { mean: f32, std_dev: f32 },
+    /// Completion whose logarithm is normally distributed with parameters `mu` and `sigma`.
+    LogNormal { mu: f32, sigma: f32 },
+    /// Gamma distributed completion with the given shape and scale.
+    Gamma { shape: f32, scale: f32 },
+    /// Weibull distributed completion with the given shape and scale.
+    Weibull { shape: f32, scale: f32 },
+    /// Pareto distributed completion with minimum `scale` and tail index `shape`.
+    Pareto { scale: f32, shape: f32 },
+}
+
+impl Distribution {
+    /// Check that the parameters describe a valid distribution.
+    pub fn validate(&self) -> Result<(), CDFError> {
+        fn check(ok: bool, msg: &str) -> Result<(), CDFError> {
+            if ok {
+                Ok(())
+            } else {
+                Err(CDFError::InvalidParameter(msg.to_owned()))
+            }
+        }
+        let positive = |x: f32| x > 0.0 && x.is_finite();
+        let non_negative = |x: f32| x >= 0.0 && x.is_finite();
+        match *self {
+            Distribution::Deterministic { delay } => {
+                check(non_negative(delay), "delay must be non-negative")
+            }
+            Distribution::Uniform { min, max } => {
+                check(non_negative(min), "min must be non-negative")?;
+                check(max.is_finite() && max > min, "max must be greater than min")
+            }
+            Distribution::Exponential { rate } => check(positive(rate), "rate must be positive"),
+            Distribution::ShiftedExponential { shift, rate } => {
+                check(non_negative(shift), "shift must be non-negative")?;
+                check(positive(rate), "rate must be positive")
+            }
+            Distribution::Normal { mean, std_dev } => {
+                check(mean.is_finite(), "mean must be finite")?;
+                check(positive(std_dev), "std_dev must be positive")
+            }
+            Distribution::LogNormal { mu, sigma } => {
+                check(mu.is_finite(), "mu must be finite")?;
+                check(positive(sigma), "sigma must be positive")
+            }
+            Distribution::Gamma { shape, scale } | Distribution::Weibull { shape, scale } => {
+                check(positive(shape), "shape must be positive")?;
+                check(positive(scale), "scale must be positive")
+            }
+            Distribution::Pareto { scale, shape } => {
+                check(positive(scale), "scale must be positive")?;
+                check(positive(shape), "shape must be positive")
+            }
+        }
+    }
+
+    /// Get the probability of completing at or before the given time.
+    pub fn probability_by(&self, time: f64) -> f64 {
+        if time < 0.0 {
+            return 0.0;
+        }
+        let p = match *self {
+            Distribution::Deterministic { delay } => {
+                // tolerate rounding errors in computing the time from a bin index
+                if delay as f64 <= time * (1.0 + 1e-6) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Distribution::Uniform { min, max } => (time - min as f64) / (max as f64 - min as f64),
+            Distribution::Exponential { rate } => 1.0 - (-(rate as f64) * time).exp(),
+            Distribution::ShiftedExponential { shift, rate } => {
+                let time = time - shift as f64;
+                if time <= 0.0 {
+                    0.0
+                } else {
+                    1.0 - (-(rate as f64) * time).exp()
+                }
+            }
+            Distribution::Normal { mean, std_dev } => {
+                let (mean, std_dev) = (mean as f64, std_dev as f64);
+                // computed from the upper tails, which keeps precision for negative means
+                let above_zero = normal_tail(-mean / std_dev);
+                if above_zero == 0.0 {
+                    // the mean is so far below zero that all mass is concentrated at zero
+                    1.0
+                } else {
+                    1.0 - normal_tail((time - mean) / std_dev) / above_zero
+                }
+            }
+            Distribution::LogNormal { mu, sigma } => {
+                normal_cdf((time.ln() - mu as f64) / sigma as f64)
+            }
+            Distribution::Gamma { shape, scale } => {
+                lower_regularized_gamma(shape as f64, time / scale as f64)
+            }
+            Distribution::Weibull { shape, scale } => {
+                1.0 - (-(time / scale as f64).powf(shape as f64)).exp()
+            }
+            Distribution::Pareto { scale, shape } => {
+                let scale = scale as f64;
+                if time <= scale {
+                    0.0
+                } else {
+                    1.0 - (scale / time).powf(shape as f64)
+                }
+            }
+        };
+        p.clamp(0.0, 1.0)
+    }
+
+    /// Discretise the distribution onto `bins` bins of the given size.
+    ///
+    /// The outcome fails with probability `failure`, all other probability mass is scaled down
+    /// accordingly; this allows creating an improper CDF in one call. Mass beyond the horizon
+    /// is recorded as truncated mass.
+    ///
+    /// The resulting CDF uses [`Precision::Float64`](crate::Precision::Float64) so that no
+    /// precision is lost before it is used in an evaluation.
+    pub fn to_cdf(&self, bin_size: f32, bins: usize, failure: f32) -> Result<CDF, CDFError> {
+        self.validate()?;
+        if !(0.0..=1.0).contains(&failure) {
+            return Err(CDFError::InvalidFraction);
+        }
+        let success = 1.0 - failure as f64;
+        let mut last = 0.0;
+        let data = (0..bins)
+            .map(|i| {
+                let p = success * self.probability_by(i as f64 * bin_size as f64);
+                // guard against numerical noise breaking monotonicity
+                last = p.max(last);
+                last
+            })
+            .collect();
+        CDF::from_probabilities(data, success - last, bin_size)
+    }
+}
+
+/// Prints the distribution in the syntax understood by its `FromStr` implementation, e.g.
+/// `exp(rate=10)` or `uniform(min=0.1, max=0.3)`.
+impl std::fmt::Display for Distribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Distribution::Deterministic { delay } => write!(f, "deterministic(delay={})", delay),
+            Distribution::Uniform { min, max } => write!(f, "uniform(min={}, max={})", min, max),
+            Distribution::Exponential { rate } => write!(f, "exp(rate={})", rate),
+            Distribution::ShiftedExponential { shift, rate } => {
+                write!(f, "shifted_exp(shift={}, rate={})", shift, rate)
+            }
+            Distribution::Normal { mean, std_dev } => {
+                write!(f, "normal(mean={}, std_dev={})", mean, std_dev)
+            }
+            Distribution::LogNormal { mu, sigma } => {
+                write!(f, "lognormal(mu={}, sigma={})", mu, sigma)
+            }
+            Distribution::Gamma { shape, scale } => {
+                write!(f, "gamma(shape={}, scale={})", shape, scale)
+            }
+            Distribution::Weibull { shape, scale } => {
+                write!(f, "weibull(shape={}, scale={})", shape, scale)
+            }
+            Distribution::Pareto { scale, shape } => {
+                write!(f, "pareto(scale={}, shape={})", scale, shape)
+            }
+        }
+    }
+}
+
+/// The CDF of the standard normal distribution.
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / SQRT_2)
+}
+
+/// The upper tail `1 - normal_cdf(x)` of the standard normal distribution, without the
+/// cancellation for large `x`.
+fn normal_tail(x: f64) -> f64 {
+    0.5 * erfc(x / SQRT_2)
+}
+
+/// The complementary error function, with a relative error below 1.2e-7 everywhere.
+///
+/// This is the Chebyshev approximation from Numerical Recipes.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t
+        * (-z * z - 1.26551223
+            + t * (1.00002368
+                + t * (0.37409196
+                    + t * (0.09678418
+                        + t * (-0.18628806
+                            + t * (0.27886807
+                                + t * (-1.13520398
+                                    + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+            .exp();
+    if x >= 0.0 {
+        r
+    } else {
+        2.0 - r
+    }
+}
+
+/// The natural logarithm of the gamma function, using the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 8] = [
+        676.5203681218851,
+        -1259.1392167224028,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507343278686905,
+        -0.13857109526572012,
+        9.984_369_578_019_572e-6,
+        1.5056327351493116e-7,
+    ];
+    if x < 0.5 {
+        // reflection formula
+        (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x)
+    } else {
+        let x = x - 1.0;
+        let t = x + 7.5;
+        let sum = COEFFICIENTS
+            .iter()
+            .enumerate()
+            .fold(0.999_999_999_999_809_9, |acc, (i, c)| {
+                acc + c / (x + i as f64 + 1.0)
+            });
+        0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+    }
+}
+
+/// The regularized lower incomplete gamma function P(a, x).
+///
+/// Uses the series expansion for small `x` and the continued fraction otherwise.
+fn lower_regularized_gamma(a: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-14;
+    const MAX_ITERATIONS: usize = 1000;
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let prefactor = (a * x.ln() - x - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..MAX_ITERATIONS {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        sum * prefactor
+    } else {
+        // modified Lentz's method for the continued fraction of Q(a, x)
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for n in 1..MAX_ITERATIONS {
+            let an = -(n as f64) * (n as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        1.0 - prefactor * h
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        let cdf = Distribution::Deterministic { delay: 0.3 }
+            .to_cdf(0.1, 5, 0.0)
+            .unwrap();
+        assert_eq!(cdf, CDF::step(&[(0.3, 1.0)], 0.1, 5).unwrap());
+        let cdf = Distribution::Deterministic { delay: 0.3 }
+            .to_cdf(0.1, 5, 0.25)
+            .unwrap();
+        assert_eq!(cdf.to_string(), "CDF[(0.3000, 0.7500)]");
+        assert_eq!(format!("{:.4}", cdf.failure_probability()), "0.2500");
+        let cdf = Distribution::Deterministic { delay: 0.3 }
+            .to_cdf(0.1, 2, 0.25)
+            .unwrap();
+        assert_eq!(cdf.to_string(), "CDF[]");
+        assert_eq!(format!("{:.4}", cdf.truncated_mass()), "0.7500");
+    }
+
+    #[test]
+    fn test_uniform() {
+        let cdf = Distribution::Uniform { min: 1.0, max: 3.0 }
+            .to_cdf(0.5, 8, 0.0)
+            .unwrap();
+        assert_eq!(
+            cdf.to_string(),
+            "CDF[(1.5000, 0.2500), (2.0000, 0.5000), (2.5000, 0.7500), (3.0000, 1.0000)]"
+        );
+        // the mass within each bin is rounded up to its end, adding half a bin to the mean
+        assert_eq!(cdf.mean(), Some(2.25));
+    }
+
+    #[test]
+    fn test_exponential() {
+        let cdf = Distribution::Exponential { rate: 2.0 }
+            .to_cdf(0.001, 10_000, 0.0)
+            .unwrap();
+        assert!((cdf.mean().unwrap() - 0.5005).abs() < 1e-4);
+        assert_eq!(format!("{:.3}", cdf.quantile(0.5).unwrap()), "0.347");
+        assert!(cdf.truncated_mass() < 1e-8);
+
+        let shifted = Distribution::ShiftedExponential {
+            shift: 1.0,
+            rate: 2.0,
+        }
+        .to_cdf(0.001, 10_000, 0.1)
+        .unwrap();
+        assert!((shifted.mean().unwrap() - 1.5005).abs() < 1e-4);
+        assert_eq!(shifted.probability_at(0.999), 0.0);
+        assert!(shifted.probability_at(1.001) > 0.0);
+        assert_eq!(format!("{:.4}", shifted.completion_probability()), "0.9000");
+    }
+
+    #[test]
+    fn test_normal() {
+        let cdf = Distribution::Normal {
+            mean: 1.0,
+            std_dev: 0.1,
+        }
+        .to_cdf(0.001, 3000, 0.0)
+        .unwrap();
+        assert_eq!(format!("{:.2}", cdf.mean().unwrap()), "1.00");
+        assert_eq!(format!("{:.3}", cdf.variance().unwrap().sqrt()), "0.100");
+        assert_eq!(format!("{:.3}", cdf.quantile(0.5).unwrap()), "1.000");
+
+        // truncated at zero, so half of the mass is moved to positive times
+        let cdf = Distribution::Normal {
+            mean: 0.0,
+            std_dev: 1.0,
+        }
+        .to_cdf(0.001, 10_000, 0.0)
+        .unwrap();
+        assert_eq!(format!("{:.3}", cdf.quantile(0.6827).unwrap()), "1.001");
+
+        // a mean far below zero puts all mass at zero
+        let cdf = Distribution::Normal {
+            mean: -50.0,
+            std_dev: 1.0,
+        }
+        .to_cdf(0.1, 10, 0.0)
+        .unwrap();
+        assert_eq!(cdf.probability_at(0.0), 1.0);
+        assert_eq!(cdf.truncated_mass(), 0.0);
+        let cdf = Distribution::Normal {
+            mean: -5.0,
+            std_dev: 1.0,
+        }
+        .to_cdf(0.01, 1000, 0.0)
+        .unwrap();
+        assert!(cdf.probability_at(0.5) > 0.9);
+        assert!(cdf.truncated_mass() < 1e-6);
+    }
+
+    #[test]
+    fn test_log_normal() {
+        let cdf = Distribution::LogNormal {
+            mu: 0.0,
+            sigma: 0.5,
+        }
+        .to_cdf(0.001, 20_000, 0.0)
+        .unwrap();
+        assert_eq!(format!("{:.3}", cdf.quantile(0.5).unwrap()), "1.000");
+        assert_eq!(format!("{:.3}", cdf.mean().unwrap()), "1.134");
+    }
+
+    #[test]
+    fn test_gamma_and_weibull() {
+        let exponential = Distribution::Exponential { rate: 0.5 }
+            .to_cdf(0.01, 1000, 0.0)
+            .unwrap();
+        let gamma = Distribution::Gamma {
+            shape: 1.0,
+            scale: 2.0,
+        }
+        .to_cdf(0.01, 1000, 0.0)
+        .unwrap();
+        let weibull = Distribution::Weibull {
+            shape: 1.0,
+            scale: 2.0,
+        }
+        .to_cdf(0.01, 1000, 0.0)
+        .unwrap();
+        assert_eq!(gamma.to_string(), exponential.to_string());
+        assert_eq!(weibull.to_string(), exponential.to_string());
+
+        let gamma = Distribution::Gamma {
+            shape: 3.0,
+            scale: 0.5,
+        }
+        .to_cdf(0.001, 20_000, 0.0)
+        .unwrap();
+        assert_eq!(format!("{:.3}", gamma.mean().unwrap()), "1.500");
+        assert_eq!(format!("{:.3}", gamma.variance().unwrap()), "0.750");
+    }
+
+    #[test]
+    fn test_pareto() {
+        let cdf = Distribution::Pareto {
+            scale: 1.0,
+            shape: 2.0,
+        }
+        .to_cdf(0.5, 8, 0.0)
+        .unwrap();
+        assert_eq!(
+            cdf.to_string(),
+            "CDF[(1.5000, 0.5556), (2.0000, 0.7500), (2.5000, 0.8400), (3.0000, 0.8889), (3.5000, 0.9184)]"
+        );
+        assert_eq!(format!("{:.4}", cdf.truncated_mass()), "0.0816");
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            Distribution::Exponential { rate: 0.0 }.to_cdf(0.1, 10, 0.0),
+            Err(CDFError::InvalidParameter(
+                "rate must be positive".to_owned()
+            ))
+        );
+        assert_eq!(
+            Distribution::Uniform { min: 2.0, max: 1.0 }.to_cdf(0.1, 10, 0.0),
+            Err(CDFError::InvalidParameter(
+                "max must be greater than min".to_owned()
+            ))
+        );
+        assert_eq!(
+            Distribution::Deterministic { delay: 1.0 }.to_cdf(0.1, 10, 1.5),
+            Err(CDFError::InvalidFraction)
+        );
+        assert_eq!(
+            Distribution::Deterministic { delay: 1.0 }.to_cdf(0.0, 10, 0.0),
+            Err(CDFError::InvalidBinSize)
+        );
+    }
+}
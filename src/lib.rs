@@ -8,10 +8,23 @@ macro_rules! cloned {
 
 mod cdf;
 mod delta_q;
+mod distribution;
+mod load;
+mod monte_carlo;
+mod parser;
 #[cfg(feature = "web")]
 mod render;
+mod requirement;
 
-pub use cdf::{CDFError, CDF};
-pub use delta_q::{DeltaQ, EvaluationContext};
+pub use cdf::{CDFError, CDFLiteral, Precision, Rounding, Summary, CDF};
+pub use delta_q::{
+    Assumption, DeltaQ, DeltaQError, EvaluationContext, Fixpoint, Location, Operator, Report, Step,
+};
+pub use distribution::Distribution;
+pub use load::{Load, LoadReport};
+pub use parser::{ParseError, Position, Span};
 #[cfg(feature = "web")]
-pub use render::{cdf_to_svg, DeltaQComponent, DeltaQContext};
+pub use render::{cdf_to_svg, summary_to_html, DeltaQComponent, DeltaQContext};
+pub use requirement::{
+    PointSlack, ProbabilitySlack, Requirement, Slack, TimeSlack, Verdict, Violation,
+};
@@ -0,0 +1,581 @@
+use crate::delta_q::{check_weights, DeltaQError, Operator, Step};
+use crate::monte_carlo::unroll;
+use crate::{CDFError, DeltaQ, Distribution, EvaluationContext, CDF};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The distribution of the amount of a resource consumed by one occurrence of an outcome.
+///
+/// The amount is a discrete random variable, given by its possible values in increasing order
+/// together with their probabilities, which sum to one.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Load {
+    amounts: Vec<(f32, f32)>,
+}
+
+impl Load {
+    /// The load of always consuming the given amount.
+    pub fn constant(amount: f32) -> Self {
+        Self {
+            amounts: vec![(amount, 1.0)],
+        }
+    }
+
+    /// The possible amounts in increasing order, each with its probability.
+    pub fn amounts(&self) -> &[(f32, f32)] {
+        &self.amounts
+    }
+
+    /// The expected amount consumed.
+    pub fn expected(&self) -> f32 {
+        self.amounts.iter().map(|(amount, p)| amount * p).sum()
+    }
+
+    /// The largest amount that is consumed with positive probability.
+    pub fn max(&self) -> f32 {
+        self.amounts.last().map_or(0.0, |(amount, _)| *amount)
+    }
+
+    /// The smallest amount that is not exceeded with at least the given probability.
+    pub fn quantile(&self, probability: f32) -> f32 {
+        let mut cumulative = 0.0;
+        for (amount, p) in &self.amounts {
+            cumulative += p;
+            // allow for rounding errors in the sum of probabilities
+            if cumulative >= probability - 1e-6 {
+                return *amount;
+            }
+        }
+        self.max()
+    }
+
+    /// The load of independent occurrences of both outcomes, i.e. the distribution of the sum.
+    fn add(&self, other: &Load) -> Load {
+        Self::collect(
+            self.amounts
+                .iter()
+                .flat_map(|&(a, p)| other.amounts.iter().map(move |&(b, q)| (a + b, p * q))),
+        )
+    }
+
+    /// The load of taking this outcome with the given probability and the other one otherwise.
+    fn mix(&self, fraction: f32, other: &Load) -> Load {
+        Self::collect(
+            self.amounts.iter().map(|&(a, p)| (a, p * fraction)).chain(
+                other
+                    .amounts
+                    .iter()
+                    .map(|&(b, q)| (b, q * (1.0 - fraction))),
+            ),
+        )
+    }
+
+    /// Sort the given amounts, merging equal ones and dropping impossible ones.
+    fn collect(amounts: impl Iterator<Item = (f32, f32)>) -> Load {
+        let mut amounts = amounts.filter(|(_, p)| *p > 0.0).collect::<Vec<_>>();
+        amounts.sort_by(|a, b| a.0.total_cmp(&b.0));
+        amounts.dedup_by(|next, previous| {
+            let merge = next.0 == previous.0;
+            if merge {
+                previous.1 += next.1;
+            }
+            merge
+        });
+        Load { amounts }
+    }
+}
+
+/// Combine the loads of two parts of an outcome per resource, where a resource not used by one
+/// of the parts has a load of zero there.
+fn combine(
+    first: BTreeMap<String, Load>,
+    second: BTreeMap<String, Load>,
+    f: impl Fn(&Load, &Load) -> Load,
+) -> BTreeMap<String, Load> {
+    let zero = Load::constant(0.0);
+    let resources = first.keys().chain(second.keys()).cloned();
+    resources
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|resource| {
+            let load = f(
+                first.get(&resource).unwrap_or(&zero),
+                second.get(&resource).unwrap_or(&zero),
+            );
+            (resource, load)
+        })
+        .collect()
+}
+
+/// The maximal number of iterations of [`EvaluationContext::eval_under_load`].
+const MAX_ITERATIONS: usize = 100;
+
+/// The change in utilisation below which load and timing are considered to agree.
+const TOLERANCE: f32 = 1e-4;
+
+/// The time spent waiting for a resource with the given utilisation by a use with the given
+/// service time, discretised with the given bin size onto at most `max_size` bins.
+///
+/// This is the waiting time of an M/M/1 queue whose mean service time is that of the use: no
+/// waiting with probability `1 - utilisation`, otherwise an exponentially distributed time
+/// with rate `(1 - utilisation) / service_time`. A saturated resource (utilisation of one or
+/// more) serves only the fraction `1 / utilisation` of the uses, without waiting, such that it
+/// carries exactly its capacity; all other uses fail.
+pub(crate) fn waiting_time(
+    utilisation: f32,
+    service_time: f32,
+    bin_size: f32,
+    max_size: usize,
+) -> Result<CDF, CDFError> {
+    if utilisation >= 1.0 {
+        return CDF::new(&[1.0 / utilisation], bin_size);
+    }
+    let immediate = CDF::new(&[1.0], bin_size)?;
+    if utilisation <= 0.0 || service_time <= 0.0 {
+        return Ok(immediate);
+    }
+    let rate = (1.0 - utilisation) / service_time;
+    // cover all but a thousandth of the waiting times, the rest is truncated mass
+    let horizon = 1000f32.ln() / rate;
+    let bins = ((horizon / bin_size).ceil() as usize + 1).clamp(1, max_size);
+    let waiting = Distribution::Exponential { rate }.to_cdf(bin_size, bins, 0.0)?;
+    immediate.choice(1.0 - utilisation, &waiting)
+}
+
+/// The outcome of evaluating a name under load, see [`EvaluationContext::eval_under_load`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadReport {
+    /// The CDF of the name, including the time spent waiting for resources.
+    pub cdf: CDF,
+    /// The load on each resource caused by one occurrence of the outcome.
+    pub load: BTreeMap<String, Load>,
+    /// The utilisation of each resource with a capacity, i.e. the expected amount requested per
+    /// unit of time relative to the capacity.
+    pub utilisation: BTreeMap<String, f32>,
+    /// The resources whose utilisation is one or more; only the fraction `1 / utilisation` of
+    /// their uses is served, the others are counted as failures.
+    pub saturated: BTreeSet<String>,
+    /// The number of iterations performed.
+    pub iterations: usize,
+    /// Whether load and timing agreed within the maximal number of iterations.
+    pub converged: bool,
+}
+
+impl EvaluationContext {
+    /// Compute the load on each resource caused by one occurrence of the outcome of the given
+    /// name, see [`DeltaQ::eval_load`].
+    pub fn eval_load(&mut self, name: &str) -> Result<BTreeMap<String, Load>, DeltaQError> {
+        DeltaQ::name(name).eval_load(self)
+    }
+
+    /// Evaluate the given name with its outcome occurring `rate` times per unit of time, taking
+    /// into account the time spent waiting for resources with a capacity (see
+    /// [`set_capacity`](Self::set_capacity)).
+    ///
+    /// The utilisation of each resource follows from the rate and the load on it, and it
+    /// determines the waiting time added to each annotated outcome using the resource (see
+    /// [`DeltaQ::Uses`]). Since the timing in turn affects the load (e.g. an outcome that fails
+    /// does not start the rest of its sequence), both are recomputed until the utilisation
+    /// they yield agrees with the one they were computed for. The updates are damped, since
+    /// uses failing at a saturated resource may reduce the load such that a plain iteration
+    /// oscillates around the fixed point.
+    ///
+    /// The computed values of this context are not affected.
+    pub fn eval_under_load(&self, name: &str, rate: f32) -> Result<LoadReport, DeltaQError> {
+        if !(rate.is_finite() && rate >= 0.0) {
+            return Err(CDFError::InvalidParameter(format!(
+                "rate must be non-negative, found {}",
+                rate
+            ))
+            .into());
+        }
+        if let Some((resource, capacity)) = self
+            .capacities()
+            .find(|(_, capacity)| !(capacity.is_finite() && *capacity > 0.0))
+        {
+            return Err(CDFError::InvalidParameter(format!(
+                "capacity of {} must be positive, found {}",
+                resource, capacity
+            ))
+            .into());
+        }
+        let mut ctx = self.clone();
+        // start from the unloaded resources
+        let mut utilisation = self
+            .capacities()
+            .map(|(resource, _)| (resource.clone(), 0.0))
+            .collect::<BTreeMap<_, _>>();
+        ctx.set_utilisation(utilisation.clone());
+        let mut iterations = 0;
+        let converged = loop {
+            iterations += 1;
+            let load = ctx.eval_load(name)?;
+            let next = self
+                .capacities()
+                .map(|(resource, capacity)| {
+                    let expected = load.get(resource).map_or(0.0, Load::expected);
+                    (resource.clone(), rate * expected / capacity)
+                })
+                .collect::<BTreeMap<_, _>>();
+            let converged = next
+                .iter()
+                .all(|(resource, u)| (u - utilisation[resource]).abs() <= TOLERANCE);
+            if converged || iterations == MAX_ITERATIONS {
+                break converged;
+            }
+            let damping = if iterations == 1 { 1.0 } else { 0.5 };
+            for (resource, u) in next {
+                let current = utilisation.get_mut(&resource).expect("same resources");
+                *current += damping * (u - *current);
+            }
+            ctx.set_utilisation(utilisation.clone());
+        };
+        let saturated = utilisation
+            .iter()
+            .filter(|(_, u)| **u >= 1.0)
+            .map(|(resource, _)| resource.clone())
+            .collect();
+        Ok(LoadReport {
+            cdf: ctx.eval(name)?,
+            load: ctx.eval_load(name)?,
+            utilisation,
+            saturated,
+            iterations,
+            converged,
+        })
+    }
+}
+
+impl DeltaQ {
+    /// Compute the load on each resource caused by one occurrence of this outcome, from the
+    /// resource usage annotations (see [`DeltaQ::Uses`]) it contains and the definitions it
+    /// refers to.
+    ///
+    /// The resources of an annotated outcome are consumed whenever it is started, whether it
+    /// completes or not. All operands of a quantification are started, while a choice starts
+    /// only one of them according to the weights. The second part of a sequence is started only
+    /// if the first part completes, which requires evaluating the CDF of the latter; likewise
+    /// the fallback of a timeout is started only if the deadline is missed. All parts
+    /// are assumed to be independent, as for [`DeltaQ::eval`], and recursive definitions are
+    /// not supported.
+    pub fn eval_load(
+        &self,
+        ctx: &mut EvaluationContext,
+    ) -> Result<BTreeMap<String, Load>, DeltaQError> {
+        self.load(ctx, &mut Vec::new())
+    }
+
+    /// Compute the load of this DeltaQ as the operand with the given index of an operator,
+    /// locating any error accordingly.
+    ///
+    /// `stack` holds the names currently being resolved, for detecting recursion.
+    fn load_operand(
+        &self,
+        operator: Operator,
+        index: usize,
+        ctx: &mut EvaluationContext,
+        stack: &mut Vec<String>,
+    ) -> Result<BTreeMap<String, Load>, DeltaQError> {
+        self.load(ctx, stack)
+            .map_err(|e| e.within(Step { operator, index }))
+    }
+
+    fn load(
+        &self,
+        ctx: &mut EvaluationContext,
+        stack: &mut Vec<String>,
+    ) -> Result<BTreeMap<String, Load>, DeltaQError> {
+        Ok(match self {
+            DeltaQ::BlackBox | DeltaQ::Assumed(_) | DeltaQ::CDF(_) => BTreeMap::new(),
+            DeltaQ::Name(name) => {
+                if let Some(pos) = stack.iter().position(|x| x == name) {
+                    let mut path = stack[pos..].to_vec();
+                    path.push(name.clone());
+                    return Err(DeltaQError::Cycle(path));
+                }
+                let delta_q = ctx
+                    .get(name)
+                    .ok_or_else(|| DeltaQError::NameError(name.clone()))?
+                    .clone();
+                stack.push(name.clone());
+                let load = delta_q.load(ctx, stack);
+                stack.pop();
+                load.map_err(|e| e.in_definition(name))?
+            }
+            DeltaQ::Uses(delta_q, usage) => {
+                if let Some((resource, amount)) = usage
+                    .iter()
+                    .find(|(_, amount)| !(amount.is_finite() && **amount >= 0.0))
+                {
+                    return Err(CDFError::InvalidParameter(format!(
+                        "usage of {} must be non-negative, found {}",
+                        resource, amount
+                    ))
+                    .into());
+                }
+                let usage = usage
+                    .iter()
+                    .map(|(resource, amount)| (resource.clone(), Load::constant(*amount)))
+                    .collect();
+                let load = delta_q.load_operand(Operator::Uses, 0, ctx, stack)?;
+                combine(load, usage, Load::add)
+            }
+            DeltaQ::Seq(first, second) => {
+                let first_load = first.load_operand(Operator::Seq, 0, ctx, stack)?;
+                let second_load = second.load_operand(Operator::Seq, 1, ctx, stack)?;
+                let completion = first
+                    .eval(ctx)
+                    .map_err(|e| {
+                        e.within(Step {
+                            operator: Operator::Seq,
+                            index: 0,
+                        })
+                    })?
+                    .completion_probability();
+                let zero = Load::constant(0.0);
+                combine(first_load, second_load, |first, second| {
+                    first.add(&second.mix(completion, &zero))
+                })
+            }
+            DeltaQ::Timeout(delta_q, _, None) => {
+                delta_q.load_operand(Operator::Timeout, 0, ctx, stack)?
+            }
+            DeltaQ::Timeout(delta_q, deadline, Some(fallback)) => {
+                let load = delta_q.load_operand(Operator::Timeout, 0, ctx, stack)?;
+                let fallback_load = fallback.load_operand(Operator::Timeout, 1, ctx, stack)?;
+                let in_time = delta_q
+                    .eval(ctx)
+                    .and_then(|cdf| Ok(cdf.timeout(*deadline, None)?))
+                    .map_err(|e| {
+                        e.within(Step {
+                            operator: Operator::Timeout,
+                            index: 0,
+                        })
+                    })?
+                    .completion_probability();
+                let zero = Load::constant(0.0);
+                combine(load, fallback_load, |load, fallback| {
+                    load.add(&zero.mix(in_time, fallback))
+                })
+            }
+            DeltaQ::Choice(first, first_weight, second, second_weight) => {
+                let first_load = first.load_operand(Operator::Choice, 0, ctx, stack)?;
+                let second_load = second.load_operand(Operator::Choice, 1, ctx, stack)?;
+                let fraction = *first_weight / (*first_weight + *second_weight);
+                combine(first_load, second_load, |first, second| {
+                    first.mix(fraction, second)
+                })
+            }
+            DeltaQ::ChoiceN(branches) => {
+                let weights = branches.iter().map(|(w, _)| *w).collect::<Vec<_>>();
+                check_weights(&weights, "choice")?;
+                // the mixture of the loads of the branches so far, with its total weight
+                let mut mixture: Option<(BTreeMap<String, Load>, f32)> = None;
+                for (index, (weight, branch)) in branches.iter().enumerate() {
+                    let load = branch.load_operand(Operator::ChoiceN, index, ctx, stack)?;
+                    if *weight == 0.0 {
+                        continue;
+                    }
+                    mixture = Some(match mixture {
+                        None => (load, *weight),
+                        Some((mix, total)) => {
+                            let fraction = total / (total + weight);
+                            let mix = combine(mix, load, |mix, load| mix.mix(fraction, load));
+                            (mix, total + weight)
+                        }
+                    });
+                }
+                mixture.expect("positive sum of weights").0
+            }
+            DeltaQ::ForAllN(operands) | DeltaQ::ForSomeN(operands) | DeltaQ::KOfN(_, operands) => {
+                let operator = match self {
+                    DeltaQ::ForAllN(_) => Operator::ForAllN,
+                    DeltaQ::ForSomeN(_) => Operator::ForSomeN,
+                    _ => Operator::KOfN,
+                };
+                let mut load = BTreeMap::new();
+                for (index, operand) in operands.iter().enumerate() {
+                    let operand = operand.load_operand(operator, index, ctx, stack)?;
+                    load = combine(load, operand, Load::add);
+                }
+                load
+            }
+            DeltaQ::ForAll(first, second) => {
+                let first_load = first.load_operand(Operator::ForAll, 0, ctx, stack)?;
+                let second_load = second.load_operand(Operator::ForAll, 1, ctx, stack)?;
+                combine(first_load, second_load, Load::add)
+            }
+            DeltaQ::ForSome(first, second) => {
+                let first_load = first.load_operand(Operator::ForSome, 0, ctx, stack)?;
+                let second_load = second.load_operand(Operator::ForSome, 1, ctx, stack)?;
+                combine(first_load, second_load, Load::add)
+            }
+            // the unrolled expression has no counterpart in the original one, so errors are
+            // located at the repetition
+            DeltaQ::Repeat(hole, base, count, template) => unroll(hole, base, *count, template)
+                .load(ctx, stack)
+                .map_err(DeltaQError::at_root)?,
+            DeltaQ::RepeatRandom(hole, base, weights, template) => {
+                check_weights(weights, "repetition")?;
+                // the mixture of the loads for all counts so far, with its total weight
+                let mut mixture: Option<(BTreeMap<String, Load>, f32)> = None;
+                for (count, &weight) in weights.iter().enumerate() {
+                    if weight == 0.0 {
+                        continue;
+                    }
+                    let load = unroll(hole, base, count, template)
+                        .load(ctx, stack)
+                        .map_err(DeltaQError::at_root)?;
+                    mixture = Some(match mixture {
+                        None => (load, weight),
+                        Some((mix, total)) => {
+                            let fraction = total / (total + weight);
+                            let mix = combine(mix, load, |mix, load| mix.mix(fraction, load));
+                            (mix, total + weight)
+                        }
+                    });
+                }
+                mixture.expect("positive sum of weights").0
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compare the amounts of a load, allowing for the rounding of probabilities in CDFs.
+    fn assert_amounts(load: &Load, expected: &[(f32, f32)]) {
+        assert_eq!(load.amounts().len(), expected.len(), "{:?}", load);
+        for ((amount, p), (expected_amount, expected_p)) in load.amounts().iter().zip(expected) {
+            assert_eq!(amount, expected_amount, "{:?}", load);
+            assert!((p - expected_p).abs() < 1e-4, "{:?}", load);
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let mut ctx: EvaluationContext = "
+            link = uses(CDF[(0.1, 1); bin_size=0.1, bins=3]; net=1500)
+            lossy = uses(CDF[(0.1, 0.5); bin_size=0.1, bins=3]; net=100)
+            model = all(link | link) 1:3 lossy -> uses(link; cpu=2)
+        "
+        .parse()
+        .unwrap();
+        let load = ctx.eval_load("model").unwrap();
+        assert_amounts(
+            &load["net"],
+            &[(100.0, 0.375), (1600.0, 0.375), (3000.0, 0.25)],
+        );
+        assert!((load["net"].expected() - 1387.5).abs() < 0.1);
+        assert_eq!(load["net"].quantile(0.5), 1600.0);
+        assert_eq!(load["net"].max(), 3000.0);
+        assert_amounts(&load["cpu"], &[(0.0, 0.625), (2.0, 0.375)]);
+        let flat = ctx.get("model").unwrap().flatten_choice();
+        assert_eq!(flat.eval_load(&mut ctx).unwrap(), load);
+
+        // the timing is not affected by the annotations
+        let mut plain: EvaluationContext = "
+            link = CDF[(0.1, 1); bin_size=0.1, bins=3]
+            lossy = CDF[(0.1, 0.5); bin_size=0.1, bins=3]
+            model = all(link | link) 1:3 lossy -> link
+        "
+        .parse()
+        .unwrap();
+        assert_eq!(ctx.eval("model"), plain.eval("model"));
+    }
+
+    #[test]
+    fn test_load_timeout() {
+        let mut ctx: EvaluationContext = "
+            req = uses(CDF[(0.1, 0.5), (0.3, 1); bin_size=0.1, bins=4]; net=100)
+            model = timeout(req; 0.2 | timeout(req; 0.2))
+        "
+        .parse()
+        .unwrap();
+        // the retry is sent whenever the first request misses its deadline
+        let load = ctx.eval_load("model").unwrap();
+        assert_amounts(&load["net"], &[(100.0, 0.5), (200.0, 0.5)]);
+    }
+
+    #[test]
+    fn test_load_repeat() {
+        let mut ctx: EvaluationContext = "
+            link = uses(CDF[(0.1, 1); bin_size=0.1, bins=3]; net=1500)
+            fixed = rep(x = link, 2 | x -> link)
+            random = rep(x = link, [1, 0, 1] | x -> link)
+        "
+        .parse()
+        .unwrap();
+        let load = ctx.eval_load("fixed").unwrap();
+        assert_amounts(&load["net"], &[(4500.0, 1.0)]);
+        let load = ctx.eval_load("random").unwrap();
+        assert_amounts(&load["net"], &[(1500.0, 0.5), (4500.0, 0.5)]);
+    }
+
+    #[test]
+    fn test_eval_under_load() {
+        let mut ctx: EvaluationContext = "
+            server = uses(CDF[(0.1, 1); bin_size=0.01, bins=20]; cpu=0.05)
+            model = server -> uses(CDF[(0.1, 1); bin_size=0.01, bins=20]; cpu=0.05)
+        "
+        .parse()
+        .unwrap();
+        ctx.set_capacity("cpu".to_owned(), 1.0);
+        let unloaded = ctx.eval("server").unwrap();
+
+        let report = ctx.eval_under_load("server", 10.0).unwrap();
+        assert!(report.converged);
+        assert!(report.saturated.is_empty());
+        assert!((report.utilisation["cpu"] - 0.5).abs() < 1e-3);
+        // the mean waiting time is 0.05, give or take the discretisation
+        let delay = report.cdf.mean().unwrap() - unloaded.mean().unwrap();
+        assert!((delay - 0.05).abs() < 0.015, "{}", delay);
+        assert!((report.cdf.completion_probability() - 1.0).abs() < 1e-3);
+        // the context itself is not affected
+        assert_eq!(ctx.eval("server").unwrap(), unloaded);
+
+        let report = ctx.eval_under_load("server", 0.0).unwrap();
+        assert_eq!(report.cdf, unloaded);
+        assert_eq!(report.utilisation["cpu"], 0.0);
+
+        // the saturated resource serves a fraction a = 1/u of the uses, and the second step is
+        // only started after the first one succeeded: u = 0.75 (1 + a)
+        let report = ctx.eval_under_load("model", 15.0).unwrap();
+        assert!(report.converged);
+        assert_eq!(report.saturated, BTreeSet::from(["cpu".to_owned()]));
+        let u = (0.75 + (0.75f32 * 0.75 + 3.0).sqrt()) / 2.0;
+        assert!((report.utilisation["cpu"] - u).abs() < 1e-3);
+        assert!((report.cdf.completion_probability() - 1.0 / (u * u)).abs() < 1e-3);
+
+        // below saturation both steps run
+        let report = ctx.eval_under_load("model", 9.0).unwrap();
+        assert!(report.converged);
+        assert!(report.saturated.is_empty());
+        assert!((report.utilisation["cpu"] - 0.9).abs() < 1e-3);
+
+        ctx.set_capacity("cpu".to_owned(), 0.0);
+        assert_eq!(
+            ctx.eval_under_load("server", 1.0).unwrap_err().to_string(),
+            "CDF error: Invalid parameter: capacity of cpu must be positive, found 0"
+        );
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let mut ctx: EvaluationContext = "
+            negative = uses(CDF[(0.1, 1); bin_size=0.1, bins=3]; net=-1)
+            open = ? -> uses(CDF[(0.1, 1); bin_size=0.1, bins=3]; net=1)
+        "
+        .parse()
+        .unwrap();
+        assert_eq!(
+            ctx.eval_load("negative").unwrap_err().to_string(),
+            "CDF error: Invalid parameter: usage of net must be non-negative, found -1 at `negative`"
+        );
+        assert_eq!(
+            ctx.eval_load("open").unwrap_err().to_string(),
+            "Black box encountered at left of Seq in `open`"
+        );
+    }
+}
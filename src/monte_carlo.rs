@@ -0,0 +1,452 @@
+use crate::delta_q::{check_operands, check_weights, DeltaQError, Operator, Step};
+use crate::{Assumption, DeltaQ, EvaluationContext, CDF};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// A DeltaQ expression with all names resolved, ready for drawing samples.
+enum Simulation {
+    Leaf(CDF),
+    Seq(Box<Simulation>, Box<Simulation>),
+    Choice(Box<Simulation>, f64, Box<Simulation>),
+    ForAll(Box<Simulation>, Box<Simulation>),
+    ForSome(Box<Simulation>, Box<Simulation>),
+    /// A choice among several simulations, each with its cumulative probability.
+    Mixture(Vec<(f64, Simulation)>),
+    /// Waiting for the given number of outcomes among several simulations.
+    KOfN(usize, Vec<Simulation>),
+    /// Giving up at the deadline, then running the fallback if any.
+    Timeout(Box<Simulation>, f64, Option<Box<Simulation>>),
+}
+
+impl Simulation {
+    /// Resolve all names in the given expression, inlining their definitions.
+    ///
+    /// `stack` holds the names currently being resolved, for detecting recursion.
+    fn new(
+        delta_q: &DeltaQ,
+        ctx: &EvaluationContext,
+        stack: &mut Vec<String>,
+    ) -> Result<Self, DeltaQError> {
+        let mut operand = |delta_q: &DeltaQ, operator, index| {
+            Self::new(delta_q, ctx, stack).map_err(|e| e.within(Step { operator, index }))
+        };
+        Ok(match delta_q {
+            DeltaQ::BlackBox => return Err(DeltaQError::BlackBox),
+            // like the analytic evaluation, use the pessimistic bound of an envelope
+            DeltaQ::Assumed(
+                assumption @ (Assumption::Exact(cdf) | Assumption::Envelope { lower: cdf, .. }),
+            ) => {
+                assumption.validate()?;
+                Simulation::Leaf(cdf.clone())
+            }
+            DeltaQ::Name(name) => {
+                if let Some(pos) = stack.iter().position(|x| x == name) {
+                    let mut path = stack[pos..].to_vec();
+                    path.push(name.clone());
+                    return Err(DeltaQError::Cycle(path));
+                }
+                let delta_q = ctx
+                    .get(name)
+                    .ok_or_else(|| DeltaQError::NameError(name.clone()))?;
+                stack.push(name.clone());
+                let simulation = Self::new(delta_q, ctx, stack);
+                stack.pop();
+                simulation.map_err(|e| e.in_definition(name))?
+            }
+            DeltaQ::CDF(cdf) => Simulation::Leaf(cdf.clone()),
+            DeltaQ::Uses(delta_q, _) => operand(delta_q, Operator::Uses, 0)?,
+            DeltaQ::Timeout(delta_q, deadline, fallback) => {
+                if !(*deadline >= 0.0 && deadline.is_finite()) {
+                    return Err(crate::CDFError::InvalidParameter(format!(
+                        "deadline must be non-negative, found {}",
+                        deadline
+                    ))
+                    .into());
+                }
+                let delta_q = Box::new(operand(delta_q, Operator::Timeout, 0)?);
+                let fallback = match fallback {
+                    Some(fallback) => Some(Box::new(operand(fallback, Operator::Timeout, 1)?)),
+                    None => None,
+                };
+                Simulation::Timeout(delta_q, *deadline as f64, fallback)
+            }
+            DeltaQ::Seq(first, second) => {
+                let first = Box::new(operand(first, Operator::Seq, 0)?);
+                let second = Box::new(operand(second, Operator::Seq, 1)?);
+                Simulation::Seq(first, second)
+            }
+            DeltaQ::Choice(first, first_weight, second, second_weight) => {
+                let first = Box::new(operand(first, Operator::Choice, 0)?);
+                let second = Box::new(operand(second, Operator::Choice, 1)?);
+                let fraction =
+                    *first_weight as f64 / (*first_weight as f64 + *second_weight as f64);
+                Simulation::Choice(first, fraction, second)
+            }
+            DeltaQ::ChoiceN(branches) => {
+                let weights = branches.iter().map(|(w, _)| *w).collect::<Vec<_>>();
+                let total = check_weights(&weights, "choice")? as f64;
+                let mut cumulative = 0.0;
+                let mut choices = Vec::new();
+                for (index, (weight, branch)) in branches.iter().enumerate() {
+                    cumulative += *weight as f64 / total;
+                    choices.push((cumulative, operand(branch, Operator::ChoiceN, index)?));
+                }
+                Simulation::Mixture(choices)
+            }
+            DeltaQ::ForAllN(operands) => {
+                check_operands(operands.len(), operands.len(), "quantification")?;
+                let operands = operands
+                    .iter()
+                    .enumerate()
+                    .map(|(index, o)| operand(o, Operator::ForAllN, index))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Simulation::KOfN(operands.len(), operands)
+            }
+            DeltaQ::ForSomeN(operands) => {
+                check_operands(1, operands.len(), "quantification")?;
+                let operands = operands
+                    .iter()
+                    .enumerate()
+                    .map(|(index, o)| operand(o, Operator::ForSomeN, index))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Simulation::KOfN(1, operands)
+            }
+            DeltaQ::KOfN(k, operands) => {
+                check_operands(*k, operands.len(), "k out of n")?;
+                let operands = operands
+                    .iter()
+                    .enumerate()
+                    .map(|(index, o)| operand(o, Operator::KOfN, index))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Simulation::KOfN(*k, operands)
+            }
+            DeltaQ::ForAll(first, second) => {
+                let first = Box::new(operand(first, Operator::ForAll, 0)?);
+                let second = Box::new(operand(second, Operator::ForAll, 1)?);
+                Simulation::ForAll(first, second)
+            }
+            DeltaQ::ForSome(first, second) => {
+                let first = Box::new(operand(first, Operator::ForSome, 0)?);
+                let second = Box::new(operand(second, Operator::ForSome, 1)?);
+                Simulation::ForSome(first, second)
+            }
+            // the unrolled expression has no counterpart in the original one, so errors are
+            // located at the repetition
+            DeltaQ::Repeat(hole, base, count, template) => {
+                Self::new(&unroll(hole, base, *count, template), ctx, stack)
+                    .map_err(DeltaQError::at_root)?
+            }
+            DeltaQ::RepeatRandom(hole, base, weights, template) => {
+                let total = check_weights(weights, "repetition")? as f64;
+                let mut cumulative = 0.0;
+                let mut choices = Vec::new();
+                for (count, &weight) in weights.iter().enumerate() {
+                    if weight > 0.0 {
+                        cumulative += weight as f64 / total;
+                        let unrolled = unroll(hole, base, count, template);
+                        let simulation =
+                            Self::new(&unrolled, ctx, stack).map_err(DeltaQError::at_root)?;
+                        choices.push((cumulative, simulation));
+                    }
+                }
+                Simulation::Mixture(choices)
+            }
+        })
+    }
+
+    /// The smallest bin size of all CDFs in the expression.
+    fn bin_size(&self) -> f32 {
+        match self {
+            Simulation::Leaf(cdf) => cdf.bin_size(),
+            Simulation::Mixture(choices) => choices
+                .iter()
+                .map(|(_, s)| s.bin_size())
+                .fold(f32::INFINITY, f32::min),
+            Simulation::KOfN(_, operands) => operands
+                .iter()
+                .map(Simulation::bin_size)
+                .fold(f32::INFINITY, f32::min),
+            Simulation::Timeout(delta_q, _, fallback) => fallback
+                .as_ref()
+                .map_or(f32::INFINITY, |fallback| fallback.bin_size())
+                .min(delta_q.bin_size()),
+            Simulation::Seq(first, second)
+            | Simulation::Choice(first, _, second)
+            | Simulation::ForAll(first, second)
+            | Simulation::ForSome(first, second) => first.bin_size().min(second.bin_size()),
+        }
+    }
+
+    /// Draw the time at which the outcome occurs, or `None` if it fails.
+    fn sample(&self, rng: &mut impl Rng) -> Option<f64> {
+        match self {
+            Simulation::Leaf(cdf) => cdf.sample_f64(rng),
+            Simulation::Seq(first, second) => Some(first.sample(rng)? + second.sample(rng)?),
+            Simulation::Choice(first, fraction, second) => {
+                if rng.gen::<f64>() < *fraction {
+                    first.sample(rng)
+                } else {
+                    second.sample(rng)
+                }
+            }
+            Simulation::ForAll(first, second) => {
+                let first = first.sample(rng);
+                let second = second.sample(rng);
+                Some(first?.max(second?))
+            }
+            Simulation::Mixture(choices) => {
+                let uniform = rng.gen::<f64>();
+                choices
+                    .iter()
+                    .find(|(cumulative, _)| uniform < *cumulative)
+                    .or(choices.last())
+                    .and_then(|(_, simulation)| simulation.sample(rng))
+            }
+            Simulation::KOfN(k, operands) => {
+                let mut times = operands
+                    .iter()
+                    .filter_map(|operand| operand.sample(rng))
+                    .collect::<Vec<_>>();
+                times.sort_by(f64::total_cmp);
+                times.get(k - 1).copied()
+            }
+            Simulation::Timeout(delta_q, deadline, fallback) => match delta_q.sample(rng) {
+                // tolerate rounding errors in sums of bin times
+                Some(time) if time <= *deadline * (1.0 + 1e-6) => Some(time),
+                _ => Some(deadline + fallback.as_ref()?.sample(rng)?),
+            },
+            Simulation::ForSome(first, second) => match (first.sample(rng), second.sample(rng)) {
+                (Some(first), Some(second)) => Some(first.min(second)),
+                (first, second) => first.or(second),
+            },
+        }
+    }
+}
+
+/// Spell out a repetition by substituting the template into itself.
+///
+/// The result grows linearly with the count if the hole occurs once in the template, but
+/// exponentially otherwise.
+pub(crate) fn unroll(hole: &str, base: &DeltaQ, count: usize, template: &DeltaQ) -> DeltaQ {
+    (0..count).fold(base.clone(), |acc, _| template.substitute(hole, &acc))
+}
+
+impl DeltaQ {
+    /// Estimate the CDF of this DeltaQ by simulating the given number of runs, drawing from the
+    /// CDFs at the leaves with a generator seeded with `seed`.
+    ///
+    /// Every occurrence of a name is drawn independently, which is the same assumption that
+    /// [`DeltaQ::eval`] makes; comparing both results thus validates the analytic evaluation.
+    /// The result uses the smallest bin size found among the leaves and is cut off at the
+    /// context's [`max_size`](EvaluationContext::max_size), with later runs recorded as
+    /// truncated mass.
+    pub fn simulate(
+        &self,
+        ctx: &EvaluationContext,
+        runs: usize,
+        seed: u64,
+    ) -> Result<CDF, DeltaQError> {
+        if runs == 0 {
+            return Err(crate::CDFError::InvalidParameter("no runs requested".to_owned()).into());
+        }
+        let simulation = Simulation::new(self, ctx, &mut Vec::new())?;
+        let bin_size = simulation.bin_size();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut counts = Vec::new();
+        let mut beyond = 0;
+        for _ in 0..runs {
+            let Some(time) = simulation.sample(&mut rng) else {
+                continue;
+            };
+            // tolerate rounding errors from summing up times with different bin sizes
+            let index = (time / bin_size as f64 + 1e-4).floor();
+            if index < ctx.max_size() as f64 {
+                let index = index as usize;
+                if counts.len() <= index {
+                    counts.resize(index + 1, 0usize);
+                }
+                counts[index] += 1;
+            } else {
+                beyond += 1;
+            }
+        }
+        let mut cumulative = 0;
+        let data = counts
+            .into_iter()
+            .map(|count| {
+                cumulative += count;
+                cumulative as f64 / runs as f64
+            })
+            .collect();
+        Ok(CDF::from_probabilities(
+            data,
+            beyond as f64 / runs as f64,
+            bin_size,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Distribution;
+    use maplit::btreemap;
+
+    #[test]
+    fn test_sample_cdf() {
+        let cdf = CDF::step(&[(0.2, 0.5), (0.5, 0.75)], 0.1, 4).unwrap();
+        let samples = cdf.samples(42).take(10_000).collect::<Vec<_>>();
+        let count = |x: Option<f32>| samples.iter().filter(|s| **s == x).count();
+        let fraction = |x: Option<f32>| count(x) as f32 / samples.len() as f32;
+        assert!((fraction(Some(0.2)) - 0.5).abs() < 0.02);
+        assert!((fraction(Some(f32::INFINITY)) - 0.25).abs() < 0.02);
+        assert!((fraction(None) - 0.25).abs() < 0.02);
+        assert_eq!(
+            samples.len(),
+            count(Some(0.2)) + count(Some(f32::INFINITY)) + count(None)
+        );
+        assert_eq!(cdf.samples(42).take(10).collect::<Vec<_>>(), samples[..10]);
+    }
+
+    #[test]
+    fn test_simulate_matches_eval() {
+        let exp = Distribution::Exponential { rate: 10.0 };
+        let ctx = btreemap! {
+            "hop".to_owned() => DeltaQ::cdf(exp.to_cdf(0.01, 100, 0.01).unwrap()),
+            "slow".to_owned() => DeltaQ::cdf(CDF::step(&[(0.1, 0.5), (0.3, 1.0)], 0.02, 20).unwrap()),
+            "model".to_owned() => DeltaQ::choice(
+                DeltaQ::seq(DeltaQ::name("hop"), DeltaQ::name("hop")),
+                1.0,
+                DeltaQ::for_all(
+                    DeltaQ::name("slow"),
+                    DeltaQ::for_some(DeltaQ::name("hop"), DeltaQ::name("slow")),
+                ),
+                2.0,
+            ),
+        };
+        let mut ctx: EvaluationContext = ctx.into();
+        let analytic = ctx.eval("model").unwrap();
+        let simulated = DeltaQ::name("model").simulate(&ctx, 100_000, 1).unwrap();
+        assert_eq!(simulated.bin_size(), 0.01);
+        assert!(analytic.kolmogorov_smirnov(&simulated).unwrap() < 0.01);
+        assert_eq!(
+            simulated,
+            DeltaQ::name("model").simulate(&ctx, 100_000, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_simulate_choice_n() {
+        let mut ctx: EvaluationContext = "
+            fast = CDF[(0.1, 1); bin_size=0.1, bins=4]
+            slow = CDF[(0.2, 1); bin_size=0.1, bins=4]
+            lossy = CDF[(0.3, 0.5); bin_size=0.1, bins=4]
+            model = choice(1: fast | 2: slow | 1: lossy)
+        "
+        .parse()
+        .unwrap();
+        let analytic = ctx.eval("model").unwrap();
+        let simulated = DeltaQ::name("model").simulate(&ctx, 100_000, 1).unwrap();
+        assert!(analytic.kolmogorov_smirnov(&simulated).unwrap() < 0.01);
+    }
+
+    #[test]
+    fn test_simulate_k_of_n() {
+        let mut ctx: EvaluationContext = "
+            hop = exp(rate=10; bin_size=0.01, bins=100, failure=0.1)
+            slow = CDF[(0.1, 0.5), (0.3, 1); bin_size=0.02, bins=20]
+            model = 2 of [hop | slow | hop -> hop] -> any[hop | slow] -> all[hop | hop]
+        "
+        .parse()
+        .unwrap();
+        let analytic = ctx.eval("model").unwrap();
+        let simulated = DeltaQ::name("model").simulate(&ctx, 100_000, 1).unwrap();
+        assert!(analytic.kolmogorov_smirnov(&simulated).unwrap() < 0.01);
+    }
+
+    #[test]
+    fn test_simulate_timeout() {
+        let mut ctx: EvaluationContext = "
+            hop = exp(rate=10; bin_size=0.01, bins=100, failure=0.1)
+            slow = CDF[(0.1, 0.5), (0.3, 0.9); bin_size=0.02, bins=20]
+            model = timeout(hop -> slow; 0.2 | timeout(hop -> slow; 0.2 | slow)) -> timeout(hop; 0.1)
+        "
+        .parse()
+        .unwrap();
+        let analytic = ctx.eval("model").unwrap();
+        let simulated = DeltaQ::name("model").simulate(&ctx, 100_000, 1).unwrap();
+        assert!(analytic.kolmogorov_smirnov(&simulated).unwrap() < 0.01);
+    }
+
+    #[test]
+    fn test_simulate_repeat() {
+        let template = DeltaQ::choice(
+            DeltaQ::name("hop"),
+            1.0,
+            DeltaQ::seq(DeltaQ::name("hop"), DeltaQ::name("x")),
+            3.0,
+        );
+        let ctx = btreemap! {
+            "hop".to_owned() => DeltaQ::cdf(CDF::step(&[(0.1, 0.5), (0.2, 1.0)], 0.1, 3).unwrap()),
+            "fixed".to_owned() => DeltaQ::repeat("x", DeltaQ::name("hop"), 3, template.clone()),
+            "random".to_owned() => DeltaQ::repeat_random("x", DeltaQ::name("hop"), vec![1.0, 0.0, 2.0], template),
+        };
+        let mut ctx: EvaluationContext = ctx.into();
+        for name in ["fixed", "random"] {
+            let analytic = ctx.eval(name).unwrap();
+            let simulated = DeltaQ::name(name).simulate(&ctx, 100_000, 7).unwrap();
+            assert!(analytic.kolmogorov_smirnov(&simulated).unwrap() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_simulate_truncation() {
+        let ctx = btreemap! {
+            "step".to_owned() => DeltaQ::cdf(CDF::step(&[(0.5, 1.0)], 0.1, 10).unwrap()),
+        };
+        let mut ctx: EvaluationContext = ctx.into();
+        ctx.set_max_size(8);
+        let model = DeltaQ::seq(DeltaQ::name("step"), DeltaQ::name("step"));
+        let simulated = model.simulate(&ctx, 100, 0).unwrap();
+        assert_eq!(simulated.to_string(), "CDF[]");
+        assert_eq!(simulated.truncated_mass(), 1.0);
+    }
+
+    #[test]
+    fn test_simulate_errors() {
+        let ctx: EvaluationContext = btreemap! {
+            "a".to_owned() => DeltaQ::seq(DeltaQ::name("b"), DeltaQ::name("a")),
+            "b".to_owned() => DeltaQ::BlackBox,
+            "c".to_owned() => DeltaQ::name("d"),
+        }
+        .into();
+        assert_eq!(
+            DeltaQ::name("a")
+                .simulate(&ctx, 10, 0)
+                .unwrap_err()
+                .to_string(),
+            "Black box encountered at `b`"
+        );
+        assert_eq!(
+            DeltaQ::name("c").simulate(&ctx, 10, 0).unwrap_err().cause(),
+            &DeltaQError::NameError("d".to_owned())
+        );
+        let ctx: EvaluationContext = btreemap! {
+            "a".to_owned() => DeltaQ::seq(DeltaQ::name("a"), DeltaQ::name("a")),
+        }
+        .into();
+        assert_eq!(
+            DeltaQ::name("a")
+                .simulate(&ctx, 10, 0)
+                .unwrap_err()
+                .to_string(),
+            "Cycle: a -> a at left of Seq in `a`"
+        );
+        for empty in [
+            DeltaQ::for_all_n(vec![]),
+            DeltaQ::for_some_n(vec![]),
+            DeltaQ::k_of_n(1, vec![]),
+        ] {
+            assert!(empty.simulate(&ctx, 10, 0).is_err());
+        }
+    }
+}
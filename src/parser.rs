@@ -0,0 +1,1243 @@
+//! Parser for the textual syntax of DeltaQ expressions.
+//!
+//! The syntax is the one produced by the `Display` implementation of [`DeltaQ`], with ASCII
+//! alternatives for all symbols:
+//!
+//! | construct        | symbolic                  | ASCII                     |
+//! |------------------|---------------------------|---------------------------|
+//! | black box        | `■`                       | `?`                       |
+//! | assumed outcome  | `■(CDF[…])`, `■(lower, upper)` | `?(…)`               |
+//! | sequence         | `A •->-• B`               | `A -> B`                  |
+//! | choice           | `A 1⇌100 B`               | `A 1:100 B`               |
+//! | n-ary choice     | `⇌(1: A | 2: B | 3: C)`   | `choice(1: A | 2: B | 3: C)` |
+//! | n-ary all        | `∀[A | B | C]`            | `all[A | B | C]`          |
+//! | n-ary some       | `∃[A | B | C]`            | `any[A | B | C]`          |
+//! | k out of n       | `2 of [A | B | C]`        |                           |
+//! | all              | `∀(A | B)`                | `all(A | B)`              |
+//! | some             | `∃(A | B)`                | `any(A | B)`              |
+//! | repetition       | `rep(x = A, 3 | B)`       |                           |
+//! | random repetition| `rep(x = A, [1, 2] | B)`  |                           |
+//! | resource usage   | `uses(A; link=1500, cpu=0.002)` |                     |
+//! | timeout          | `timeout(A; 0.5)`, `timeout(A; 0.5 | B)` |               |
+//! | CDF              | `CDF[(0.1, 0.5), (0.2, 1); bin_size=0.1, bins=5]`     |
+//! | distribution     | `exp(rate=10; bin_size=0.01, bins=100, failure=0.01)` |
+//!
+//! A CDF literal lists the time and value of each step, followed by the settings `bin_size` and
+//! `bins` as well as the optional `truncated` (mass beyond the horizon) and `precision`
+//! (`fixed16` or `float64`); this is what [`CDF::literal`] prints, so
+//! CDFs survive a round trip through text without loss. A parametric [`Distribution`] is given
+//! by its name and parameters (see its `Display` implementation), followed by the settings
+//! `bin_size`, `bins` and optionally `failure` that are passed to [`Distribution::to_cdf`]. It
+//! is turned into a CDF while parsing, so printing the expression yields a CDF literal.
+//! In both cases `bins` is limited to [`CDF::DEFAULT_MAX_SIZE`].
+//!
+//! A black box may carry a provisional outcome, either a single CDF (literal or distribution) or
+//! a pessimistic and an optimistic one, see [`Assumption`](crate::Assumption).
+//!
+//! Sequence binds more strongly than choice and both associate to the right, so that
+//! `A -> B 1:2 C -> D` is a choice between two sequences. Parentheses may be used for grouping.
+//! Names consist of letters, digits and underscores and must not start with a digit.
+//!
+//! A whole [`EvaluationContext`] is written as a list of definitions `name = expression`.
+//! Comments start with `#` and extend to the end of the line.
+use crate::{DeltaQ, Distribution, EvaluationContext, Precision, CDF};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// A position in the parsed text, with 1-based line and column (counted in characters).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A range of the parsed text, from `start` up to (excluding) `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl std::error::Error for ParseError {}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.start.line, self.span.start.column, self.message
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Number(f64),
+    BlackBox,
+    Seq,
+    Choice,
+    All,
+    Some,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Pipe,
+    Comma,
+    Semicolon,
+    Equals,
+    Eof,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Name(name) => write!(f, "name `{}`", name),
+            Token::Number(n) => write!(f, "number `{}`", n),
+            Token::BlackBox => write!(f, "`■`"),
+            Token::Seq => write!(f, "`->`"),
+            Token::Choice => write!(f, "`:`"),
+            Token::All => write!(f, "`∀`"),
+            Token::Some => write!(f, "`∃`"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::LBracket => write!(f, "`[`"),
+            Token::RBracket => write!(f, "`]`"),
+            Token::Pipe => write!(f, "`|`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Semicolon => write!(f, "`;`"),
+            Token::Equals => write!(f, "`=`"),
+            Token::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, Span)>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut pos = Position { line: 1, column: 1 };
+    // advance over `n` characters, keeping track of the position
+    let advance = |i: &mut usize, pos: &mut Position, n: usize| {
+        for _ in 0..n {
+            if chars[*i] == '\n' {
+                pos.line += 1;
+                pos.column = 1;
+            } else {
+                pos.column += 1;
+            }
+            *i += 1;
+        }
+    };
+    let starts_with = |i: usize, s: &str| {
+        s.chars()
+            .enumerate()
+            .all(|(j, c)| chars.get(i + j) == Some(&c))
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = pos;
+        if c.is_whitespace() {
+            advance(&mut i, &mut pos, 1);
+            continue;
+        }
+        if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                advance(&mut i, &mut pos, 1);
+            }
+            continue;
+        }
+        let (token, len) = if starts_with(i, "•->-•") {
+            (Token::Seq, 5)
+        } else if starts_with(i, "->") {
+            (Token::Seq, 2)
+        } else if c.is_alphabetic() || c == '_' {
+            let len = chars[i..]
+                .iter()
+                .take_while(|c| c.is_alphanumeric() || **c == '_')
+                .count();
+            (Token::Name(chars[i..i + len].iter().collect()), len)
+        } else if c.is_ascii_digit()
+            || (c == '-' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            let sign = (c == '-') as usize;
+            let mut len = sign
+                + chars[i + sign..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .count();
+            if chars.get(i + len) == Some(&'.') {
+                len += 1;
+                len += chars[i + len..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .count();
+            }
+            if matches!(chars.get(i + len), Some('e' | 'E')) {
+                let sign = matches!(chars.get(i + len + 1), Some('+' | '-')) as usize;
+                let digits = chars[(i + len + 1 + sign).min(chars.len())..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .count();
+                if digits > 0 {
+                    len += 1 + sign + digits;
+                }
+            }
+            let text: String = chars[i..i + len].iter().collect();
+            let number = text.parse::<f64>().map_err(|e| {
+                let mut end = start;
+                end.column += len;
+                ParseError {
+                    message: format!("invalid number `{}`: {}", text, e),
+                    span: Span { start, end },
+                }
+            })?;
+            (Token::Number(number), len)
+        } else {
+            let token = match c {
+                '■' | '?' => Token::BlackBox,
+                '⇌' | ':' => Token::Choice,
+                '∀' => Token::All,
+                '∃' => Token::Some,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                '[' => Token::LBracket,
+                ']' => Token::RBracket,
+                '|' => Token::Pipe,
+                ',' => Token::Comma,
+                ';' => Token::Semicolon,
+                '=' => Token::Equals,
+                _ => {
+                    let mut end = start;
+                    end.column += 1;
+                    return Err(ParseError {
+                        message: format!("unexpected character `{}`", c),
+                        span: Span { start, end },
+                    });
+                }
+            };
+            (token, 1)
+        };
+        advance(&mut i, &mut pos, len);
+        tokens.push((token, Span { start, end: pos }));
+    }
+    tokens.push((
+        Token::Eof,
+        Span {
+            start: pos,
+            end: pos,
+        },
+    ));
+    Ok(tokens)
+}
+
+/// The names of the parametric distributions, see [`to_distribution`].
+const DISTRIBUTIONS: [&str; 9] = [
+    "deterministic",
+    "uniform",
+    "exp",
+    "shifted_exp",
+    "normal",
+    "lognormal",
+    "gamma",
+    "weibull",
+    "pareto",
+];
+
+/// A setting `key=value` of a CDF literal or a distribution.
+struct Param {
+    key: String,
+    value: Token,
+    span: Span,
+}
+
+/// The settings of a CDF literal or a distribution, which are taken out one by one.
+struct Params {
+    values: Vec<Param>,
+    /// The span of the token following the settings, for reporting missing ones.
+    end: Span,
+}
+
+impl Params {
+    fn take(&mut self, key: &str) -> Option<Param> {
+        let index = self.values.iter().position(|p| p.key == key)?;
+        Some(self.values.remove(index))
+    }
+
+    fn optional_number(&mut self, key: &str) -> Result<Option<f64>, ParseError> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(Param {
+                value: Token::Number(n),
+                ..
+            }) => Ok(Some(n)),
+            Some(Param { value, span, .. }) => Err(ParseError {
+                message: format!("expected number for `{}`, found {}", key, value),
+                span,
+            }),
+        }
+    }
+
+    fn number(&mut self, key: &str) -> Result<f64, ParseError> {
+        self.optional_number(key)?.ok_or_else(|| ParseError {
+            message: format!("missing setting `{}`", key),
+            span: self.end,
+        })
+    }
+
+    fn count(&mut self, key: &str) -> Result<usize, ParseError> {
+        let span = self.values.iter().find(|p| p.key == key).map(|p| p.span);
+        let count = self.number(key)?;
+        if count < 0.0 || count.fract() != 0.0 {
+            return Err(ParseError {
+                message: format!("`{}` must be a whole number, found {}", key, count),
+                span: span.unwrap_or(self.end),
+            });
+        }
+        Ok(count as usize)
+    }
+
+    /// The number of bins of a CDF, which must not exceed [`CDF::DEFAULT_MAX_SIZE`].
+    fn bins(&mut self) -> Result<usize, ParseError> {
+        let span = self.values.iter().find(|p| p.key == "bins").map(|p| p.span);
+        let bins = self.count("bins")?;
+        if bins > CDF::DEFAULT_MAX_SIZE {
+            return Err(ParseError {
+                message: format!(
+                    "`bins` must be at most {}, found {}",
+                    CDF::DEFAULT_MAX_SIZE,
+                    bins
+                ),
+                span: span.unwrap_or(self.end),
+            });
+        }
+        Ok(bins)
+    }
+
+    fn optional_name(&mut self, key: &str) -> Result<Option<(String, Span)>, ParseError> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(Param {
+                value: Token::Name(name),
+                span,
+                ..
+            }) => Ok(Some((name, span))),
+            Some(Param { value, span, .. }) => Err(ParseError {
+                message: format!("expected name for `{}`, found {}", key, value),
+                span,
+            }),
+        }
+    }
+
+    /// Check that all settings have been used.
+    fn finish(self) -> Result<(), ParseError> {
+        match self.values.into_iter().next() {
+            None => Ok(()),
+            Some(Param { key, span, .. }) => Err(ParseError {
+                message: format!("unknown setting `{}`", key),
+                span,
+            }),
+        }
+    }
+}
+
+/// Build the distribution of the given name (one of [`DISTRIBUTIONS`]) from its parameters.
+fn to_distribution(name: &str, mut params: Params) -> Result<Distribution, ParseError> {
+    let mut param = |key| params.number(key).map(|n| n as f32);
+    let distribution = match name {
+        "deterministic" => Distribution::Deterministic {
+            delay: param("delay")?,
+        },
+        "uniform" => Distribution::Uniform {
+            min: param("min")?,
+            max: param("max")?,
+        },
+        "exp" => Distribution::Exponential {
+            rate: param("rate")?,
+        },
+        "shifted_exp" => Distribution::ShiftedExponential {
+            shift: param("shift")?,
+            rate: param("rate")?,
+        },
+        "normal" => Distribution::Normal {
+            mean: param("mean")?,
+            std_dev: param("std_dev")?,
+        },
+        "lognormal" => Distribution::LogNormal {
+            mu: param("mu")?,
+            sigma: param("sigma")?,
+        },
+        "gamma" => Distribution::Gamma {
+            shape: param("shape")?,
+            scale: param("scale")?,
+        },
+        "weibull" => Distribution::Weibull {
+            shape: param("shape")?,
+            scale: param("scale")?,
+        },
+        "pareto" => Distribution::Pareto {
+            scale: param("scale")?,
+            shape: param("shape")?,
+        },
+        _ => unreachable!("not a distribution: {}", name),
+    };
+    params.finish()?;
+    Ok(distribution)
+}
+
+struct Parser {
+    tokens: Vec<(Token, Span)>,
+    index: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            tokens: tokenize(input)?,
+            index: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn span(&self) -> Span {
+        self.tokens[self.index].1
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.index].0.clone();
+        if self.index < self.tokens.len() - 1 {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, ParseError> {
+        Err(ParseError {
+            message: format!("expected {}, found {}", expected, self.peek()),
+            span: self.span(),
+        })
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ParseError> {
+        if *self.peek() == token {
+            self.next();
+            Ok(())
+        } else {
+            self.error(&token.to_string())
+        }
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Token::Name(name) => {
+                let name = name.clone();
+                self.next();
+                Ok(name)
+            }
+            _ => self.error("name"),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, ParseError> {
+        match self.peek() {
+            Token::Number(n) => {
+                let n = *n;
+                self.next();
+                Ok(n)
+            }
+            _ => self.error("number"),
+        }
+    }
+
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.peek() {
+            Token::Eof => Ok(()),
+            _ => self.error("end of input"),
+        }
+    }
+
+    /// expr := seq (NUMBER CHOICE NUMBER expr)?
+    fn expr(&mut self) -> Result<DeltaQ, ParseError> {
+        let first = self.seq()?;
+        if !matches!(self.peek(), Token::Number(_)) {
+            return Ok(first);
+        }
+        let first_weight = self.number()?;
+        self.expect(Token::Choice)?;
+        let second_weight = self.number()?;
+        let second = self.expr()?;
+        Ok(DeltaQ::choice(
+            first,
+            first_weight as f32,
+            second,
+            second_weight as f32,
+        ))
+    }
+
+    /// seq := atom (SEQ seq)?
+    fn seq(&mut self) -> Result<DeltaQ, ParseError> {
+        let first = self.atom()?;
+        if *self.peek() != Token::Seq {
+            return Ok(first);
+        }
+        self.next();
+        let second = self.seq()?;
+        Ok(DeltaQ::seq(first, second))
+    }
+
+    /// Parse `(A | B)` after a quantifier.
+    fn pair(&mut self) -> Result<(DeltaQ, DeltaQ), ParseError> {
+        self.expect(Token::LParen)?;
+        let first = self.expr()?;
+        self.expect(Token::Pipe)?;
+        let second = self.expr()?;
+        self.expect(Token::RParen)?;
+        Ok((first, second))
+    }
+
+    /// Parse `[A | B | …]` after an n-ary quantifier.
+    fn list(&mut self) -> Result<Vec<DeltaQ>, ParseError> {
+        self.expect(Token::LBracket)?;
+        let mut operands = vec![self.expr()?];
+        while *self.peek() == Token::Pipe {
+            self.next();
+            operands.push(self.expr()?);
+        }
+        self.expect(Token::RBracket)?;
+        Ok(operands)
+    }
+
+    /// Parse `(w0: A | w1: B | …)` after `⇌` or `choice`.
+    fn choice_n(&mut self) -> Result<DeltaQ, ParseError> {
+        self.expect(Token::LParen)?;
+        let mut branches = Vec::new();
+        loop {
+            let weight = self.number()? as f32;
+            self.expect(Token::Choice)?;
+            branches.push((weight, self.expr()?));
+            if *self.peek() != Token::Pipe {
+                break;
+            }
+            self.next();
+        }
+        self.expect(Token::RParen)?;
+        Ok(DeltaQ::choice_n(branches))
+    }
+
+    /// Parse `(x = B, n | T)` or `(x = B, [w0, w1, …] | T)` after `rep`.
+    fn repeat(&mut self) -> Result<DeltaQ, ParseError> {
+        self.expect(Token::LParen)?;
+        let hole = self.name()?;
+        self.expect(Token::Equals)?;
+        let base = self.expr()?;
+        self.expect(Token::Comma)?;
+        let weights = if *self.peek() == Token::LBracket {
+            self.next();
+            let mut weights = vec![self.number()? as f32];
+            while *self.peek() == Token::Comma {
+                self.next();
+                weights.push(self.number()? as f32);
+            }
+            self.expect(Token::RBracket)?;
+            Some(weights)
+        } else {
+            None
+        };
+        let count = match weights {
+            Some(_) => 0,
+            None => {
+                let span = self.span();
+                let count = self.number()?;
+                if count < 0.0 || count.fract() != 0.0 {
+                    return Err(ParseError {
+                        message: format!(
+                            "repetition count must be a whole number, found {}",
+                            count
+                        ),
+                        span,
+                    });
+                }
+                count as usize
+            }
+        };
+        self.expect(Token::Pipe)?;
+        let template = self.expr()?;
+        self.expect(Token::RParen)?;
+        Ok(match weights {
+            Some(weights) => DeltaQ::repeat_random(&hole, base, weights, template),
+            None => DeltaQ::repeat(&hole, base, count, template),
+        })
+    }
+
+    /// The span from the start of the given span up to the end of the previous token.
+    fn since(&self, start: Span) -> Span {
+        Span {
+            start: start.start,
+            end: self.tokens[self.index.saturating_sub(1)].1.end,
+        }
+    }
+
+    /// params := (NAME '=' (NUMBER | NAME) (',' NAME '=' (NUMBER | NAME))*)?
+    fn params(&mut self) -> Result<Params, ParseError> {
+        let mut values: Vec<Param> = Vec::new();
+        while let Token::Name(key) = self.peek().clone() {
+            let span = self.span();
+            if values.iter().any(|p| p.key == key) {
+                return Err(ParseError {
+                    message: format!("duplicate setting `{}`", key),
+                    span,
+                });
+            }
+            self.next();
+            self.expect(Token::Equals)?;
+            let value = match self.peek() {
+                Token::Number(_) | Token::Name(_) => self.next(),
+                _ => return self.error("number or name"),
+            };
+            values.push(Param {
+                key,
+                value,
+                span: self.since(span),
+            });
+            if *self.peek() != Token::Comma {
+                break;
+            }
+            self.next();
+        }
+        Ok(Params {
+            values,
+            end: self.span(),
+        })
+    }
+
+    /// Parse `(param=…, …)` after the name of a distribution.
+    fn distribution(&mut self, name: &str) -> Result<Distribution, ParseError> {
+        let start = self.span();
+        self.expect(Token::LParen)?;
+        let params = self.params()?;
+        self.expect(Token::RParen)?;
+        let distribution = to_distribution(name, params)?;
+        distribution.validate().map_err(|e| ParseError {
+            message: e.to_string(),
+            span: self.since(start),
+        })?;
+        Ok(distribution)
+    }
+
+    /// Parse `(param=…, …; bin_size=…, bins=…, failure=…)` after the name of a distribution,
+    /// turning the distribution into a CDF.
+    fn parametric(&mut self, name: &str) -> Result<CDF, ParseError> {
+        let start = self.span();
+        self.expect(Token::LParen)?;
+        let params = self.params()?;
+        self.expect(Token::Semicolon)?;
+        let mut settings = self.params()?;
+        let bin_size = settings.number("bin_size")? as f32;
+        let bins = settings.bins()?;
+        let failure = settings.optional_number("failure")?.unwrap_or(0.0) as f32;
+        settings.finish()?;
+        self.expect(Token::RParen)?;
+        to_distribution(name, params)?
+            .to_cdf(bin_size, bins, failure)
+            .map_err(|e| ParseError {
+                message: e.to_string(),
+                span: self.since(start),
+            })
+    }
+
+    /// Parse `(A; resource=amount, …)` after `uses`.
+    fn uses(&mut self) -> Result<DeltaQ, ParseError> {
+        self.expect(Token::LParen)?;
+        let delta_q = self.expr()?;
+        self.expect(Token::Semicolon)?;
+        let mut params = self.params()?;
+        let resources = params
+            .values
+            .iter()
+            .map(|p| p.key.clone())
+            .collect::<Vec<_>>();
+        let mut usage = BTreeMap::new();
+        for resource in resources {
+            let amount = params.number(&resource)? as f32;
+            usage.insert(resource, amount);
+        }
+        self.expect(Token::RParen)?;
+        Ok(DeltaQ::uses(delta_q, usage))
+    }
+
+    /// Parse `(A; deadline)` or `(A; deadline | fallback)` after `timeout`.
+    fn timeout(&mut self) -> Result<DeltaQ, ParseError> {
+        self.expect(Token::LParen)?;
+        let delta_q = self.expr()?;
+        self.expect(Token::Semicolon)?;
+        let deadline = self.number()? as f32;
+        let fallback = if *self.peek() == Token::Pipe {
+            self.next();
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        self.expect(Token::RParen)?;
+        Ok(DeltaQ::timeout(delta_q, deadline, fallback))
+    }
+
+    /// Parse a CDF literal or a distribution, as used for the assumed outcome of a black box.
+    fn leaf(&mut self) -> Result<CDF, ParseError> {
+        let start = self.span();
+        match self.atom()? {
+            DeltaQ::CDF(cdf) => Ok(cdf),
+            _ => Err(ParseError {
+                message: "expected CDF or distribution".to_owned(),
+                span: self.since(start),
+            }),
+        }
+    }
+
+    /// Parse `[(t, p), …; bin_size=…, bins=…, truncated=…, precision=…]` after `CDF`.
+    fn cdf(&mut self) -> Result<CDF, ParseError> {
+        let start = self.span();
+        self.expect(Token::LBracket)?;
+        let mut steps = Vec::new();
+        if *self.peek() == Token::LParen {
+            loop {
+                self.expect(Token::LParen)?;
+                let time = self.number()?;
+                self.expect(Token::Comma)?;
+                let value = self.number()?;
+                self.expect(Token::RParen)?;
+                steps.push((time, value));
+                if *self.peek() != Token::Comma {
+                    break;
+                }
+                self.next();
+            }
+            self.expect(Token::Semicolon)?;
+        }
+        let mut settings = self.params()?;
+        let bin_size = settings.number("bin_size")? as f32;
+        let bins = settings.bins()?;
+        let truncated = settings.optional_number("truncated")?.unwrap_or(0.0);
+        let precision = match settings.optional_name("precision")? {
+            None => Precision::default(),
+            Some((name, _)) if name == "fixed16" => Precision::Fixed16,
+            Some((name, _)) if name == "float64" => Precision::Float64,
+            Some((name, span)) => {
+                return Err(ParseError {
+                    message: format!(
+                        "expected precision `fixed16` or `float64`, found `{}`",
+                        name
+                    ),
+                    span,
+                })
+            }
+        };
+        settings.finish()?;
+        self.expect(Token::RBracket)?;
+        CDF::from_literal(&steps, bin_size, bins, truncated, precision).map_err(|e| ParseError {
+            message: e.to_string(),
+            span: self.since(start),
+        })
+    }
+
+    /// atom := NAME | BLACKBOX | '(' expr ')' | quantifier | repetition | CDF | distribution
+    fn atom(&mut self) -> Result<DeltaQ, ParseError> {
+        match self.peek().clone() {
+            Token::BlackBox => {
+                self.next();
+                if *self.peek() != Token::LParen {
+                    return Ok(DeltaQ::BlackBox);
+                }
+                self.next();
+                let first = self.leaf()?;
+                if *self.peek() == Token::Comma {
+                    self.next();
+                    let second = self.leaf()?;
+                    self.expect(Token::RParen)?;
+                    return Ok(DeltaQ::envelope(first, second));
+                }
+                self.expect(Token::RParen)?;
+                Ok(DeltaQ::assumed(first))
+            }
+            Token::LParen => {
+                self.next();
+                let dq = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(dq)
+            }
+            Token::Choice => {
+                self.next();
+                self.choice_n()
+            }
+            Token::All => {
+                self.next();
+                if *self.peek() == Token::LBracket {
+                    return Ok(DeltaQ::for_all_n(self.list()?));
+                }
+                let (first, second) = self.pair()?;
+                Ok(DeltaQ::for_all(first, second))
+            }
+            Token::Some => {
+                self.next();
+                if *self.peek() == Token::LBracket {
+                    return Ok(DeltaQ::for_some_n(self.list()?));
+                }
+                let (first, second) = self.pair()?;
+                Ok(DeltaQ::for_some(first, second))
+            }
+            Token::Number(_) => {
+                let span = self.span();
+                let k = self.number()?;
+                if k < 1.0 || k.fract() != 0.0 {
+                    return Err(ParseError {
+                        message: format!(
+                            "number of outcomes must be a positive whole number, found {}",
+                            k
+                        ),
+                        span,
+                    });
+                }
+                match self.name()?.as_str() {
+                    "of" => Ok(DeltaQ::k_of_n(k as usize, self.list()?)),
+                    _ => Err(ParseError {
+                        message: "expected `of`".to_owned(),
+                        span: self.since(span),
+                    }),
+                }
+            }
+            Token::Name(name) => {
+                self.next();
+                if *self.peek() == Token::LBracket {
+                    match name.as_str() {
+                        "CDF" => return Ok(DeltaQ::CDF(self.cdf()?)),
+                        "all" => return Ok(DeltaQ::for_all_n(self.list()?)),
+                        "any" => return Ok(DeltaQ::for_some_n(self.list()?)),
+                        _ => {}
+                    }
+                }
+                // keywords are only recognised when followed by a parenthesis
+                if *self.peek() != Token::LParen {
+                    return Ok(DeltaQ::Name(name));
+                }
+                match name.as_str() {
+                    "all" => {
+                        let (first, second) = self.pair()?;
+                        Ok(DeltaQ::for_all(first, second))
+                    }
+                    "any" => {
+                        let (first, second) = self.pair()?;
+                        Ok(DeltaQ::for_some(first, second))
+                    }
+                    "choice" => self.choice_n(),
+                    "rep" => self.repeat(),
+                    "uses" => self.uses(),
+                    "timeout" => self.timeout(),
+                    name if DISTRIBUTIONS.contains(&name) => {
+                        Ok(DeltaQ::CDF(self.parametric(name)?))
+                    }
+                    _ => self.error("operator after name"),
+                }
+            }
+            _ => self.error("expression"),
+        }
+    }
+
+    /// context := (NAME '=' expr)*
+    fn context(&mut self) -> Result<EvaluationContext, ParseError> {
+        // definitions are collected first since they may refer to later ones
+        let mut defs = BTreeMap::new();
+        while *self.peek() != Token::Eof {
+            let span = self.span();
+            let name = self.name()?;
+            if defs.contains_key(&name) {
+                return Err(ParseError {
+                    message: format!("duplicate definition of `{}`", name),
+                    span,
+                });
+            }
+            self.expect(Token::Equals)?;
+            let dq = self.expr()?;
+            defs.insert(name, dq);
+        }
+        Ok(defs.into())
+    }
+}
+
+impl FromStr for DeltaQ {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(s)?;
+        let dq = parser.expr()?;
+        parser.end()?;
+        Ok(dq)
+    }
+}
+
+impl FromStr for CDF {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(s)?;
+        if *parser.peek() != Token::Name("CDF".to_owned()) {
+            return parser.error("`CDF`");
+        }
+        parser.next();
+        let cdf = parser.cdf()?;
+        parser.end()?;
+        Ok(cdf)
+    }
+}
+
+impl FromStr for Distribution {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(s)?;
+        let span = parser.span();
+        let name = parser.name()?;
+        if !DISTRIBUTIONS.contains(&name.as_str()) {
+            return Err(ParseError {
+                message: format!(
+                    "unknown distribution `{}`, expected one of {}",
+                    name,
+                    DISTRIBUTIONS.join(", ")
+                ),
+                span,
+            });
+        }
+        let distribution = parser.distribution(&name)?;
+        parser.end()?;
+        Ok(distribution)
+    }
+}
+
+impl FromStr for EvaluationContext {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s)?.context()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> DeltaQ {
+        s.parse().unwrap()
+    }
+
+    fn parse_err(s: &str) -> String {
+        let err = s.parse::<DeltaQ>().unwrap_err();
+        format!("{}-{}:{}", err, err.span.end.line, err.span.end.column)
+    }
+
+    #[test]
+    fn test_parse_ascii() {
+        assert_eq!(parse("A"), DeltaQ::name("A"));
+        assert_eq!(parse("?"), DeltaQ::BlackBox);
+        assert_eq!(
+            parse("A -> B"),
+            DeltaQ::seq(DeltaQ::name("A"), DeltaQ::name("B"))
+        );
+        assert_eq!(
+            parse("A 1:100 B"),
+            DeltaQ::choice(DeltaQ::name("A"), 1.0, DeltaQ::name("B"), 100.0)
+        );
+        assert_eq!(
+            parse("all(A|B)"),
+            DeltaQ::for_all(DeltaQ::name("A"), DeltaQ::name("B"))
+        );
+        assert_eq!(
+            parse("any(A | B -> C)"),
+            DeltaQ::for_some(
+                DeltaQ::name("A"),
+                DeltaQ::seq(DeltaQ::name("B"), DeltaQ::name("C"))
+            )
+        );
+        assert_eq!(
+            parse("choice(1: A | 3: B -> C)"),
+            DeltaQ::choice_n(vec![
+                (1.0, DeltaQ::name("A")),
+                (3.0, DeltaQ::seq(DeltaQ::name("B"), DeltaQ::name("C")))
+            ])
+        );
+        assert_eq!(
+            parse("all[A | B | C] -> any[A]"),
+            DeltaQ::seq(
+                DeltaQ::for_all_n(vec![
+                    DeltaQ::name("A"),
+                    DeltaQ::name("B"),
+                    DeltaQ::name("C")
+                ]),
+                DeltaQ::for_some_n(vec![DeltaQ::name("A")])
+            )
+        );
+        assert_eq!(
+            parse("2 of [A | B]"),
+            DeltaQ::k_of_n(2, vec![DeltaQ::name("A"), DeltaQ::name("B")])
+        );
+        assert_eq!(
+            parse("timeout(A; 0.5 | timeout(A; 0.5)) -> B"),
+            DeltaQ::seq(
+                DeltaQ::timeout(
+                    DeltaQ::name("A"),
+                    0.5,
+                    Some(DeltaQ::timeout(DeltaQ::name("A"), 0.5, None))
+                ),
+                DeltaQ::name("B")
+            )
+        );
+        // keywords without parentheses are names
+        assert_eq!(
+            parse("all -> rep"),
+            DeltaQ::seq(DeltaQ::name("all"), DeltaQ::name("rep"))
+        );
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        assert_eq!(
+            parse("A -> B -> C"),
+            DeltaQ::seq(
+                DeltaQ::name("A"),
+                DeltaQ::seq(DeltaQ::name("B"), DeltaQ::name("C"))
+            )
+        );
+        assert_eq!(
+            parse("A -> B 0.5:1.5e1 C -> D 1:2 E"),
+            DeltaQ::choice(
+                DeltaQ::seq(DeltaQ::name("A"), DeltaQ::name("B")),
+                0.5,
+                DeltaQ::choice(
+                    DeltaQ::seq(DeltaQ::name("C"), DeltaQ::name("D")),
+                    1.0,
+                    DeltaQ::name("E"),
+                    2.0
+                ),
+                15.0
+            )
+        );
+        assert_eq!(
+            parse("(A 1:2 B) -> C"),
+            DeltaQ::seq(
+                DeltaQ::choice(DeltaQ::name("A"), 1.0, DeltaQ::name("B"), 2.0),
+                DeltaQ::name("C")
+            )
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let exprs = [
+            "A",
+            "■",
+            "A •->-• B",
+            "(A •->-• B) •->-• C",
+            "A •->-• (B 0.3⇌0.7 C)",
+            "(A 1⇌100 B) 2⇌3 (C •->-• ∀(D | E •->-• F))",
+            "∃(∀(A | B) | C 1⇌2 D)",
+            "rep(x = B, 3 | A •->-• x)",
+            "rep(x = A 1⇌2 B, [0.5, 0, 2] | ∀(x | rep(y = x, 2 | y •->-• y)))",
+            "uses(A •->-• B; cpu=0.002, link=1500) •->-• uses(C;)",
+            "⇌(1: A | 2.5: B 1⇌2 C | 0: ⇌(1: D •->-• E))",
+            "∀[A | B •->-• C | ∃[D]] •->-• 2 of [A | ∀(B | C) | D 1⇌2 E]",
+            "timeout(A •->-• B; 0.5) •->-• timeout(A; 1.5 | timeout(A; 1.5 | B 1⇌2 C))",
+        ];
+        for expr in exprs {
+            let dq = parse(expr);
+            assert_eq!(dq.to_string(), expr);
+        }
+    }
+
+    #[test]
+    fn test_parse_context() {
+        let text = "
+            # the single hop
+            single = ?
+            model2 = single 1:100 (single -> single)
+            model3 = single 1:100 (single -> model2)
+        ";
+        let ctx: EvaluationContext = text.parse().unwrap();
+        assert_eq!(ctx.get("single"), Some(&DeltaQ::BlackBox));
+        assert_eq!(
+            ctx.get("model3"),
+            Some(&parse("single 1:100 (single -> model2)"))
+        );
+        assert_eq!(ctx.iter().count(), 3);
+
+        assert_eq!(
+            ctx.to_string(),
+            "model2 = single 1⇌100 (single •->-• single)\n\
+             model3 = single 1⇌100 (single •->-• model2)\n\
+             single = ■\n"
+        );
+        assert_eq!(ctx.to_string().parse::<EvaluationContext>().unwrap(), ctx);
+
+        let err = "a = b\na = c".parse::<EvaluationContext>().unwrap_err();
+        assert_eq!(err.to_string(), "2:1: duplicate definition of `a`");
+    }
+
+    #[test]
+    fn test_parse_cdf() {
+        let cdf = CDF::step(&[(0.1, 0.5), (0.2, 1.0)], 0.1, 5).unwrap();
+        assert_eq!(
+            parse("CDF[(0.1, 0.5), (0.2, 1); bin_size=0.1, bins=5] -> A"),
+            DeltaQ::seq(DeltaQ::cdf(cdf.clone()), DeltaQ::name("A"))
+        );
+        // settings may come in any order and `CDF` without brackets is a name
+        assert_eq!(
+            parse("CDF[(0.1, 0.5), (0.2, 1); bins=5, bin_size=0.1]").to_string(),
+            "CDF[(0.1, 0.500008), (0.2, 1); bin_size=0.1, bins=5]"
+        );
+        assert_eq!(parse("CDF"), DeltaQ::name("CDF"));
+
+        let exp = Distribution::Exponential { rate: 10.0 };
+        assert_eq!(
+            parse("exp(rate=10; bin_size=0.01, bins=100, failure=0.01)"),
+            DeltaQ::cdf(exp.to_cdf(0.01, 100, 0.01).unwrap())
+        );
+        let ctx: EvaluationContext = "
+            hop = uniform(min=0.01, max=0.05; bin_size=0.01, bins=10)
+            model = CDF[(0.02, 0.25); bin_size=0.01, bins=5, truncated=0.5, precision=float64] 1:2 hop
+        "
+        .parse()
+        .unwrap();
+        let back: EvaluationContext = ctx.to_string().parse().unwrap();
+        assert_eq!(back.to_string(), ctx.to_string());
+
+        for dist in [
+            exp,
+            Distribution::ShiftedExponential {
+                shift: 0.25,
+                rate: 3.5,
+            },
+            Distribution::Normal {
+                mean: -1.0,
+                std_dev: 0.1,
+            },
+        ] {
+            assert_eq!(dist.to_string().parse::<Distribution>().unwrap(), dist);
+        }
+    }
+
+    #[test]
+    fn test_parse_assumed() {
+        let fast = CDF::step(&[(0.1, 1.0)], 0.1, 3).unwrap();
+        let slow = CDF::step(&[(0.2, 1.0)], 0.1, 3).unwrap();
+        assert_eq!(
+            parse("?(CDF[(0.1, 1); bin_size=0.1, bins=3]) -> A"),
+            DeltaQ::seq(DeltaQ::assumed(fast.clone()), DeltaQ::name("A"))
+        );
+        let envelope = DeltaQ::envelope(slow, fast);
+        assert_eq!(envelope.to_string().parse::<DeltaQ>().unwrap(), envelope);
+        assert_eq!(parse_err("?(A)"), "1:3: expected CDF or distribution-1:4");
+        assert_eq!(parse_err("?(A, B"), "1:3: expected CDF or distribution-1:4");
+    }
+
+    #[test]
+    fn test_parse_cdf_errors() {
+        let cdf_err = |s: &str| s.parse::<CDF>().unwrap_err().to_string();
+        let dist_err = |s: &str| s.parse::<Distribution>().unwrap_err().to_string();
+        assert_eq!(cdf_err("CDF[(0.1, 0.5)]"), "1:15: expected `;`, found `]`");
+        assert_eq!(
+            cdf_err("CDF[(0.1, 0.5); bins=5]"),
+            "1:23: missing setting `bin_size`"
+        );
+        assert_eq!(
+            cdf_err("CDF[bin_size=1, bins=2.5]"),
+            "1:17: `bins` must be a whole number, found 2.5"
+        );
+        assert_eq!(
+            cdf_err("CDF[bin_size=1, bins=1e18]"),
+            "1:17: `bins` must be at most 10000, found 1000000000000000000"
+        );
+        assert_eq!(
+            parse_err("exp(rate=1; bin_size=0.1, bins=10001)"),
+            "1:27: `bins` must be at most 10000, found 10001-1:37"
+        );
+        assert_eq!(
+            cdf_err("CDF[bin_size=1, bins=2, width=3]"),
+            "1:25: unknown setting `width`"
+        );
+        assert_eq!(
+            cdf_err("CDF[bin_size=1, bins=2, precision=fixed8]"),
+            "1:25: expected precision `fixed16` or `float64`, found `fixed8`"
+        );
+        assert_eq!(
+            cdf_err("CDF[(1, 0.5), (0, 1); bin_size=1, bins=2]"),
+            "1:4: Data vector must contain monotonically increasing values"
+        );
+        assert_eq!(
+            cdf_err("CDF[(1, 0.5), (2, 1); bin_size=1, bins=2]"),
+            "1:4: Data vector must contain monotonically increasing values"
+        );
+        assert_eq!(
+            dist_err("exp(rate=-1)"),
+            "1:4: Invalid parameter: rate must be positive"
+        );
+        assert_eq!(
+            dist_err("poisson(rate=1)"),
+            "1:1: unknown distribution `poisson`, expected one of deterministic, uniform, \
+             exp, shifted_exp, normal, lognormal, gamma, weibull, pareto"
+        );
+        assert_eq!(
+            dist_err("exp(rate=1; bins=3)"),
+            "1:11: expected `)`, found `;`"
+        );
+        assert_eq!(
+            parse_err("gamma(shape=2; bin_size=0.1, bins=10)"),
+            "1:14: missing setting `scale`-1:15"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse_err("A ->"),
+            "1:5: expected expression, found end of input-1:5"
+        );
+        assert_eq!(
+            parse_err("all(A B)"),
+            "1:7: expected `|`, found name `B`-1:8"
+        );
+        assert_eq!(
+            parse_err("A\n  1:x B"),
+            "2:5: expected number, found name `x`-2:6"
+        );
+        assert_eq!(parse_err("A $ B"), "1:3: unexpected character `$`-1:4");
+        assert_eq!(
+            parse_err("foo(A)"),
+            "1:4: expected operator after name, found `(`-1:5"
+        );
+        assert_eq!(
+            parse_err("rep(x = A, 1.5 | x)"),
+            "1:12: repetition count must be a whole number, found 1.5-1:15"
+        );
+        assert_eq!(
+            parse_err("1.5 of [A]"),
+            "1:1: number of outcomes must be a positive whole number, found 1.5-1:4"
+        );
+        assert_eq!(parse_err("2 off [A]"), "1:1: expected `of`-1:6");
+        assert_eq!(
+            parse_err("A B"),
+            "1:3: expected end of input, found name `B`-1:4"
+        );
+        assert_eq!(
+            parse_err("(A -> B"),
+            "1:8: expected `)`, found end of input-1:8"
+        );
+    }
+}
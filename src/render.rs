@@ -1,7 +1,8 @@
 use crate::EvaluationContext;
-use crate::{delta_q::DeltaQ, CDF};
+use crate::{delta_q::DeltaQ, Assumption, Operator, Step, Summary, CDF};
 use charts_rs::{Axis, Canvas, Color, Point, Polyline};
 use iter_tools::Itertools;
+use std::collections::BTreeMap;
 use std::rc::Rc;
 use std::sync::Arc;
 use web_sys::HtmlInputElement;
@@ -28,16 +29,32 @@ impl DeltaQContext {
 pub struct Props {
     pub delta_q: DeltaQ,
     pub on_change: Callback<(String, Option<DeltaQ>)>,
+    /// The path from this expression to the sub-expression where evaluating it failed, if the
+    /// failure lies within it.
+    #[prop_or_default]
+    pub error: Option<Vec<Step>>,
+}
+
+/// The path of an error within the given operand, if the error lies there.
+fn error_within(error: &Option<Vec<Step>>, operator: Operator, index: usize) -> Option<Vec<Step>> {
+    match error.as_deref() {
+        Some([step, rest @ ..]) if *step == (Step { operator, index }) => Some(rest.to_vec()),
+        _ => None,
+    }
 }
 
 #[function_component(DeltaQComponent)]
 pub fn delta_q_component(props: &Props) -> Html {
     web_sys::console::log_1(&wasm_bindgen::JsValue::from_str(&format!("{props:?}")));
     let on_change = props.on_change.clone();
-    match &props.delta_q {
+    let error = props.error.clone();
+    let rendered = match &props.delta_q {
         DeltaQ::BlackBox => {
             html! { <BlackBox {on_change} /> }
         }
+        DeltaQ::Assumed(assumption) => {
+            html! { <BlackBox assumption={Some(assumption.clone())} {on_change} /> }
+        }
         DeltaQ::Name(name) => {
             html! { <NameComponent name={name.clone()} {on_change} /> }
         }
@@ -45,22 +62,56 @@ pub fn delta_q_component(props: &Props) -> Html {
             html! { <div class={classes!("cdf")}>{ format!("{}", cdf) }</div> }
         }
         DeltaQ::Seq(first, second) => {
-            html!(<Seq first={(**first).clone()} second={(**second).clone()} {on_change} />)
+            html!(<Seq first={(**first).clone()} second={(**second).clone()} {on_change} {error} />)
         }
         DeltaQ::Choice(first, first_weight, second, second_weight) => {
-            html!(<Branch top={(**first).clone()} bottom={(**second).clone()} {on_change} kind={BranchKind::Choice(*first_weight, *second_weight)} />)
+            html!(<Branch top={(**first).clone()} bottom={(**second).clone()} {on_change} kind={BranchKind::Choice(*first_weight, *second_weight)} {error} />)
         }
         DeltaQ::ForAll(first, second) => {
-            html!(<Branch top={(**first).clone()} bottom={(**second).clone()} kind={BranchKind::ForAll} {on_change} />)
+            html!(<Branch top={(**first).clone()} bottom={(**second).clone()} kind={BranchKind::ForAll} {on_change} {error} />)
         }
         DeltaQ::ForSome(first, second) => {
-            html!(<Branch top={(**first).clone()} bottom={(**second).clone()} kind={BranchKind::ForSome} {on_change} />)
+            html!(<Branch top={(**first).clone()} bottom={(**second).clone()} kind={BranchKind::ForSome} {on_change} {error} />)
+        }
+        DeltaQ::Repeat(hole, base, count, template) => {
+            html!(<Repeat hole={hole.clone()} base={(**base).clone()} count={RepeatCount::Fixed(*count)} template={(**template).clone()} {on_change} {error} />)
+        }
+        DeltaQ::RepeatRandom(hole, base, weights, template) => {
+            html!(<Repeat hole={hole.clone()} base={(**base).clone()} count={RepeatCount::Random(weights.clone())} template={(**template).clone()} {on_change} {error} />)
+        }
+        DeltaQ::ChoiceN(branches) => {
+            let (weights, branches): (Vec<_>, Vec<_>) = branches.iter().cloned().unzip();
+            html!(<Branches {branches} kind={BranchesKind::Choice(weights)} {on_change} {error} />)
+        }
+        DeltaQ::ForAllN(branches) => {
+            html!(<Branches branches={branches.clone()} kind={BranchesKind::ForAll} {on_change} {error} />)
+        }
+        DeltaQ::ForSomeN(branches) => {
+            html!(<Branches branches={branches.clone()} kind={BranchesKind::ForSome} {on_change} {error} />)
+        }
+        DeltaQ::KOfN(k, branches) => {
+            html!(<Branches branches={branches.clone()} kind={BranchesKind::KOfN(*k)} {on_change} {error} />)
+        }
+        DeltaQ::Uses(delta_q, usage) => {
+            html!(<Uses delta_q={(**delta_q).clone()} usage={usage.clone()} {on_change} {error} />)
         }
+        DeltaQ::Timeout(delta_q, deadline, fallback) => {
+            html!(<Timeout delta_q={(**delta_q).clone()} deadline={*deadline} fallback={fallback.as_deref().cloned()} {on_change} {error} />)
+        }
+    };
+    // an error located at this expression itself is marked here, others within the operands
+    if props.error.as_ref().is_some_and(|path| path.is_empty()) {
+        html! { <div class={classes!("broken")}>{ rendered }</div> }
+    } else {
+        rendered
     }
 }
 
 #[derive(Properties, Clone, PartialEq)]
 pub struct BlackBoxProps {
+    /// The provisional outcome, shown to distinguish it from elaborated parts.
+    #[prop_or_default]
+    pub assumption: Option<Assumption>,
     pub on_change: Callback<(String, Option<DeltaQ>)>,
 }
 
@@ -81,8 +132,20 @@ pub fn black_box(props: &BlackBoxProps) -> Html {
         }
     ));
 
+    let (class, assumed) = match &props.assumption {
+        None => ("blackBox", None),
+        Some(Assumption::Exact(cdf)) => ("assumed", Some(format!("assumed {}", cdf))),
+        Some(Assumption::Envelope { lower, upper }) => (
+            "assumed",
+            Some(format!("assumed between {} and {}", lower, upper)),
+        ),
+    };
+
     html! {
-        <div class={classes!("blackBox", "anchor")} onclick={cloned!(popup; move |_| if !*popup { popup.set(true) })}>
+        <div class={classes!(class, "anchor")} onclick={cloned!(popup; move |_| if !*popup { popup.set(true) })}>
+            if let Some(assumed) = assumed {
+                { assumed }
+            }
             if *popup {
                 <div class={classes!("popup")}>
                     <button onclick={cloned!(popup; move |_| popup.set(false))}>{ "abort" }</button>
@@ -155,6 +218,8 @@ pub struct SeqProps {
     pub first: DeltaQ,
     pub second: DeltaQ,
     pub on_change: Callback<(String, Option<DeltaQ>)>,
+    #[prop_or_default]
+    pub error: Option<Vec<Step>>,
 }
 
 #[function_component(Seq)]
@@ -201,7 +266,7 @@ pub fn seq(props: &SeqProps) -> Html {
 
     html! {
         <div class={classes!("row", "center", "frame")}>
-            <DeltaQComponent delta_q={first.clone()} on_change={on_first_change} />
+            <DeltaQComponent delta_q={first.clone()} on_change={on_first_change} error={error_within(&props.error, Operator::Seq, 0)} />
             <div class={classes!("seqSymbol", "anchor")} onclick={cloned!(popup; move |_| if !*popup { popup.set(true) })}>
                 if *popup {
                     <div class={classes!("popup")}>
@@ -228,7 +293,7 @@ pub fn seq(props: &SeqProps) -> Html {
                     </div>
                 }
             </div>
-            <DeltaQComponent delta_q={second} on_change={on_second_change} />
+            <DeltaQComponent delta_q={second} on_change={on_second_change} error={error_within(&props.error, Operator::Seq, 1)} />
         </div>
     }
 }
@@ -239,6 +304,8 @@ pub struct BranchProps {
     pub bottom: DeltaQ,
     pub on_change: Callback<(String, Option<DeltaQ>)>,
     pub kind: BranchKind,
+    #[prop_or_default]
+    pub error: Option<Vec<Step>>,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -328,6 +395,13 @@ pub fn branch_kind_component(props: &BranchProps) -> Html {
                     popup.set(false);
                     on_change.emit((ctx.name.clone(), Some(DeltaQ::choice(bottom.clone(), *bottom_frac, top.clone(), *top_frac))))
                 })}>{ "switch" }</button>
+                if let BranchKind::Choice(top_weight, bottom_weight) = props.kind {
+                    <button onclick={cloned!(popup, on_change, top, bottom, ctx; move |_| {
+                        popup.set(false);
+                        let choice = DeltaQ::choice(top.clone(), top_weight, bottom.clone(), bottom_weight);
+                        on_change.emit((ctx.name.clone(), Some(choice.flatten_choice())))
+                    })}>{ "flatten" }</button>
+                }
                 <button onclick={cloned!(popup, on_change, top, ctx;
                     move |_| { popup.set(false); on_change.emit((ctx.name.clone(), Some(top.clone()))) })}>{ "keep top" }</button>
                 <button onclick={cloned!(popup, on_change, bottom, ctx;
@@ -359,6 +433,11 @@ fn branch(props: &BranchProps) -> Html {
         BranchKind::ForAll => Arc::new(DeltaQ::ForAll),
         BranchKind::ForSome => Arc::new(DeltaQ::ForSome),
     };
+    let operator = match kind {
+        BranchKind::Choice(..) => Operator::Choice,
+        BranchKind::ForAll => Operator::ForAll,
+        BranchKind::ForSome => Operator::ForSome,
+    };
     let ctx = use_context::<DeltaQContext>().unwrap();
 
     let on_top_change = Callback::from(cloned!(bottom, on_change, constructor, ctx;
@@ -388,11 +467,215 @@ fn branch(props: &BranchProps) -> Html {
             <BranchKindComponent ..props.clone() />
             <div class={classes!("column", "left")} style="border-left: 2px solid black;">
                 <div class={classes!("row", "left")} >
-                    <DeltaQComponent delta_q={top} on_change={on_top_change} />
+                    <DeltaQComponent delta_q={top} on_change={on_top_change} error={error_within(&props.error, operator, 0)} />
                 </div>
                 <div style="border: 1px solid black;"></div>
                 <div class={classes!("row", "left")} >
-                    <DeltaQComponent delta_q={bottom} on_change={on_bottom_change} />
+                    <DeltaQComponent delta_q={bottom} on_change={on_bottom_change} error={error_within(&props.error, operator, 1)} />
+                </div>
+            </div>
+        </div>
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum BranchesKind {
+    Choice(Vec<f32>),
+    ForAll,
+    ForSome,
+    KOfN(usize),
+}
+
+impl BranchesKind {
+    pub fn build(&self, branches: Vec<DeltaQ>) -> DeltaQ {
+        match self {
+            BranchesKind::Choice(weights) => {
+                DeltaQ::choice_n(weights.iter().copied().zip(branches).collect())
+            }
+            BranchesKind::ForAll => DeltaQ::for_all_n(branches),
+            BranchesKind::ForSome => DeltaQ::for_some_n(branches),
+            BranchesKind::KOfN(k) => DeltaQ::k_of_n(*k, branches),
+        }
+    }
+
+    /// The label shown next to the branch with the given index.
+    fn label(&self, index: usize) -> String {
+        match self {
+            BranchesKind::Choice(weights) => format!("{}", weights[index]),
+            _ => String::new(),
+        }
+    }
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct BranchesProps {
+    pub branches: Vec<DeltaQ>,
+    pub kind: BranchesKind,
+    pub on_change: Callback<(String, Option<DeltaQ>)>,
+    #[prop_or_default]
+    pub error: Option<Vec<Step>>,
+}
+
+/// A component that renders an n-ary operator like [`Branch`] does for binary ones, with the
+/// branches stacked on top of each other.
+#[function_component(Branches)]
+fn branches(props: &BranchesProps) -> Html {
+    let on_change = props.on_change.clone();
+    let branches = props.branches.clone();
+    let kind = props.kind.clone();
+    let ctx = use_context::<DeltaQContext>().unwrap();
+    let popup = use_state(|| false);
+
+    let symbol = match &kind {
+        BranchesKind::Choice(_) => "⇌".to_owned(),
+        BranchesKind::ForAll => "∀".to_owned(),
+        BranchesKind::ForSome => "∃".to_owned(),
+        BranchesKind::KOfN(k) => format!("{k} of {}", branches.len()),
+    };
+    let operator = match &kind {
+        BranchesKind::Choice(_) => Operator::ChoiceN,
+        BranchesKind::ForAll => Operator::ForAllN,
+        BranchesKind::ForSome => Operator::ForSomeN,
+        BranchesKind::KOfN(_) => Operator::KOfN,
+    };
+    let rows = branches
+        .iter()
+        .enumerate()
+        .map(|(index, branch)| {
+            let on_branch_change = Callback::from(cloned!(branches, kind, on_change, ctx;
+                move |(name, delta_q): (String, Option<DeltaQ>)| {
+                    // if the name matches our context, edit the DeltaQ; otherwise just bubble up
+                    if name != ctx.name {
+                        on_change.emit((name, delta_q));
+                    } else if let Some(delta_q) = delta_q {
+                        let mut branches = branches.clone();
+                        branches[index] = delta_q;
+                        on_change.emit((name, Some(kind.build(branches))));
+                    }
+                }
+            ));
+            html! {
+                <div class={classes!("row", "left")} style="border-bottom: 1px solid black;">
+                    <div class={classes!("branchKind")}>{ kind.label(index) }</div>
+                    <DeltaQComponent delta_q={branch.clone()} on_change={on_branch_change} error={error_within(&props.error, operator, index)} />
+                </div>
+            }
+        })
+        .collect::<Html>();
+
+    html! {
+        <div class={classes!("row", "frame")}>
+            <div class={classes!("row", "center", "branchKind", "anchor")} onclick={cloned!(popup; move |_| if !*popup { popup.set(true) })}>
+                { symbol }
+                if *popup {
+                    <div class={classes!("popup")}>
+                        <button onclick={cloned!(popup; move |_| popup.set(false))}>{ "abort" }</button>
+                        <button onclick={cloned!(popup, on_change, branches, kind, ctx; move |_| {
+                            popup.set(false);
+                            let mut branches = branches.clone();
+                            branches.push(DeltaQ::BlackBox);
+                            let kind = match &kind {
+                                BranchesKind::Choice(weights) => BranchesKind::Choice(weights.iter().copied().chain([1.0]).collect()),
+                                kind => kind.clone(),
+                            };
+                            on_change.emit((ctx.name.clone(), Some(kind.build(branches))))
+                        })}>{ "add branch" }</button>
+                        if matches!(kind, BranchesKind::Choice(_)) {
+                            <button onclick={cloned!(popup, on_change, branches, kind, ctx; move |_| {
+                                popup.set(false);
+                                on_change.emit((ctx.name.clone(), Some(kind.build(branches.clone()).nest_choice())))
+                            })}>{ "nest" }</button>
+                        }
+                        <button onclick={cloned!(on_change, ctx;
+                            move |_| on_change.emit((ctx.name.clone(), Some(DeltaQ::BlackBox))))}>{ "black box" }</button>
+                    </div>
+                }
+            </div>
+            <div class={classes!("column", "left")} style="border-left: 2px solid black;">
+                { rows }
+            </div>
+        </div>
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum RepeatCount {
+    Fixed(usize),
+    Random(Vec<f32>),
+}
+
+impl RepeatCount {
+    pub fn build(&self, hole: &str, base: DeltaQ, template: DeltaQ) -> DeltaQ {
+        match self {
+            RepeatCount::Fixed(count) => DeltaQ::repeat(hole, base, *count, template),
+            RepeatCount::Random(weights) => {
+                DeltaQ::repeat_random(hole, base, weights.clone(), template)
+            }
+        }
+    }
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct RepeatProps {
+    pub hole: String,
+    pub base: DeltaQ,
+    pub count: RepeatCount,
+    pub template: DeltaQ,
+    pub on_change: Callback<(String, Option<DeltaQ>)>,
+    #[prop_or_default]
+    pub error: Option<Vec<Step>>,
+}
+
+/// A component that renders a repetition.
+///
+/// The hole and count are shown on the left, the base and the template on the right, with the
+/// base at the top.
+#[function_component(Repeat)]
+fn repeat(props: &RepeatProps) -> Html {
+    let on_change = props.on_change.clone();
+    let hole = props.hole.clone();
+    let base = props.base.clone();
+    let count = props.count.clone();
+    let template = props.template.clone();
+    let ctx = use_context::<DeltaQContext>().unwrap();
+
+    let on_base_change = Callback::from(cloned!(hole, count, template, on_change, ctx;
+        move |(name, delta_q)| {
+            // if the name matches our context, edit the DeltaQ; otherwise just bubble up
+            if name != ctx.name {
+                on_change.emit((name, delta_q));
+            } else if let Some(delta_q) = delta_q {
+                on_change.emit((name, Some(count.build(&hole, delta_q, template.clone()))));
+            }
+        }
+    ));
+
+    let on_template_change = Callback::from(cloned!(hole, count, base, on_change, ctx;
+        move |(name, delta_q)| {
+            // if the name matches our context, edit the DeltaQ; otherwise just bubble up
+            if name != ctx.name {
+                on_change.emit((name, delta_q));
+            } else if let Some(delta_q) = delta_q {
+                on_change.emit((name, Some(count.build(&hole, base.clone(), delta_q))));
+            }
+        }
+    ));
+
+    let label = match &count {
+        RepeatCount::Fixed(count) => format!("{} ↻ {}", hole, count),
+        RepeatCount::Random(weights) => format!("{} ↻ [{}]", hole, weights.iter().join(", ")),
+    };
+
+    html! {
+        <div class={classes!("row", "frame")}>
+            <div class={classes!("column", "center")}>{ label }</div>
+            <div class={classes!("column", "left")} style="border-left: 2px solid black;">
+                <div class={classes!("row", "left")} >
+                    <DeltaQComponent delta_q={base} on_change={on_base_change} error={error_within(&props.error, Operator::Repeat, 0)} />
+                </div>
+                <div style="border: 1px solid black;"></div>
+                <div class={classes!("row", "left")} >
+                    <DeltaQComponent delta_q={template} on_change={on_template_change} error={error_within(&props.error, Operator::Repeat, 1)} />
                 </div>
             </div>
         </div>
@@ -444,21 +727,136 @@ pub fn cdf_to_svg(cdf: &CDF) -> Html {
     html! {
         <>
             <p>{ "result: " }{cdf.to_string()} </p>
+            <p>{ "bin size: " }{cdf.bin_size()}</p>
+            if cdf.truncated_mass() > 0.0 {
+                <p>{ "beyond horizon: " }{ format!("{:.4}", cdf.truncated_mass()) }</p>
+            }
             { svg }
         </>
     }
 }
 
+pub fn summary_to_html(summary: &Summary) -> Html {
+    let time = |t: Option<f32>| t.map_or_else(|| "—".to_owned(), |t| format!("{:.4}", t));
+    html! {
+        <table class={classes!("summary")}>
+            <tr><td>{ "mean" }</td><td>{ time(summary.mean) }</td></tr>
+            <tr><td>{ "std dev" }</td><td>{ time(summary.variance.map(f32::sqrt)) }</td></tr>
+            <tr><td>{ "p50" }</td><td>{ time(summary.p50) }</td></tr>
+            <tr><td>{ "p95" }</td><td>{ time(summary.p95) }</td></tr>
+            <tr><td>{ "p99" }</td><td>{ time(summary.p99) }</td></tr>
+            <tr><td>{ "p99.9" }</td><td>{ time(summary.p99_9) }</td></tr>
+            <tr><td>{ "completion" }</td><td>{ format!("{:.4}", summary.completion) }</td></tr>
+            <tr><td>{ "failure" }</td><td>{ format!("{:.4}", 1.0 - summary.completion) }</td></tr>
+        </table>
+    }
+}
+
 impl Reducible for EvaluationContext {
     type Action = (String, Option<DeltaQ>);
 
     fn reduce(self: Rc<Self>, (name, dq): Self::Action) -> Rc<Self> {
         let mut ctx = (*self).clone();
         if let Some(dq) = dq {
-            ctx.put(name, dq);
+            // a definition creating a cycle is rejected and reported before it is dispatched
+            if ctx.put(name, dq).is_err() {
+                return self;
+            }
         } else {
             ctx.remove(&name);
         }
         Rc::new(ctx)
     }
 }
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct UsesProps {
+    pub delta_q: DeltaQ,
+    pub usage: BTreeMap<String, f32>,
+    pub on_change: Callback<(String, Option<DeltaQ>)>,
+    #[prop_or_default]
+    pub error: Option<Vec<Step>>,
+}
+
+/// A component that renders a resource usage annotation, with the usage shown below the
+/// annotated DeltaQ.
+#[function_component(Uses)]
+fn uses(props: &UsesProps) -> Html {
+    let on_change = props.on_change.clone();
+    let usage = props.usage.clone();
+    let ctx = use_context::<DeltaQContext>().unwrap();
+
+    let on_inner_change = Callback::from(cloned!(usage, on_change, ctx;
+        move |(name, delta_q)| {
+            // if the name matches our context, edit the DeltaQ; otherwise just bubble up
+            if name != ctx.name {
+                on_change.emit((name, delta_q));
+            } else if let Some(delta_q) = delta_q {
+                on_change.emit((name, Some(DeltaQ::uses(delta_q, usage.clone()))));
+            }
+        }
+    ));
+
+    let label = usage
+        .iter()
+        .map(|(resource, amount)| format!("{resource}: {amount}"))
+        .join(", ");
+
+    html! {
+        <div class={classes!("column", "frame")}>
+            <DeltaQComponent delta_q={props.delta_q.clone()} on_change={on_inner_change} error={error_within(&props.error, Operator::Uses, 0)} />
+            <div class={classes!("usage")}>{ label }</div>
+        </div>
+    }
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct TimeoutProps {
+    pub delta_q: DeltaQ,
+    pub deadline: f32,
+    pub fallback: Option<DeltaQ>,
+    pub on_change: Callback<(String, Option<DeltaQ>)>,
+    #[prop_or_default]
+    pub error: Option<Vec<Step>>,
+}
+
+/// A component that renders a timeout, with the deadline and the optional fallback shown below
+/// the DeltaQ that is given up on.
+#[function_component(Timeout)]
+fn timeout(props: &TimeoutProps) -> Html {
+    let on_change = props.on_change.clone();
+    let delta_q = props.delta_q.clone();
+    let deadline = props.deadline;
+    let fallback = props.fallback.clone();
+    let ctx = use_context::<DeltaQContext>().unwrap();
+
+    let on_inner_change = Callback::from(cloned!(fallback, on_change, ctx;
+        move |(name, delta_q)| {
+            // if the name matches our context, edit the DeltaQ; otherwise just bubble up
+            if name != ctx.name {
+                on_change.emit((name, delta_q));
+            } else if let Some(delta_q) = delta_q {
+                on_change.emit((name, Some(DeltaQ::timeout(delta_q, deadline, fallback.clone()))));
+            }
+        }
+    ));
+    let on_fallback_change = Callback::from(cloned!(delta_q, on_change, ctx;
+        move |(name, fallback)| {
+            if name != ctx.name {
+                on_change.emit((name, fallback));
+            } else if let Some(fallback) = fallback {
+                on_change.emit((name, Some(DeltaQ::timeout(delta_q.clone(), deadline, Some(fallback)))));
+            }
+        }
+    ));
+
+    html! {
+        <div class={classes!("column", "frame")}>
+            <DeltaQComponent delta_q={delta_q} on_change={on_inner_change} error={error_within(&props.error, Operator::Timeout, 0)} />
+            <div class={classes!("timeout")}>{ format!("timeout after {deadline}") }</div>
+            if let Some(fallback) = fallback {
+                <DeltaQComponent delta_q={fallback} on_change={on_fallback_change} error={error_within(&props.error, Operator::Timeout, 1)} />
+            }
+        </div>
+    }
+}
@@ -0,0 +1,373 @@
+use crate::{CDFError, CDF};
+use std::fmt::{self, Display};
+
+/// A quality-attenuation requirement on an outcome, given as a step function below which the
+/// outcome's CDF must not fall.
+///
+/// Each point `(time, probability)` demands that the outcome occurs by `time` with at least
+/// `probability`, e.g. `[(0.1, 0.5), (0.5, 0.95), (2.0, 0.999)]` for “50% by 100ms, 95% by
+/// 500ms, 99.9% by 2s”.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "Vec<(f32, f32)>", into = "Vec<(f32, f32)>")]
+pub struct Requirement {
+    points: Vec<(f32, f32)>,
+}
+
+/// The outcome of checking a CDF against a [`Requirement`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Verdict {
+    Satisfied,
+    /// The requirement is not met at the listed points.
+    Violated(Vec<Violation>),
+}
+
+/// A point of a [`Requirement`] that is not met.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Violation {
+    /// The time by which the required probability should be reached.
+    pub time: f32,
+    /// The required probability.
+    pub required: f32,
+    /// The probability actually reached by `time`.
+    pub actual: f32,
+    /// How much later than `time` the required probability is reached, or `None` if it is not
+    /// reached within the horizon of the CDF.
+    pub late_by: Option<f32>,
+}
+
+/// The headroom of a CDF with respect to a [`Requirement`], see [`Requirement::slack`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Slack {
+    /// The time slack at each probability where the CDF or the requirement steps, up to the
+    /// highest required probability.
+    pub time: Vec<TimeSlack>,
+    /// The probability slack at each bin of the CDF and each point of the requirement, starting
+    /// from the first point of the requirement.
+    pub probability: Vec<ProbabilitySlack>,
+    /// The requirement point with the least headroom, if the requirement has any points.
+    pub tightest: Option<PointSlack>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TimeSlack {
+    pub probability: f32,
+    /// How much earlier than required the probability is reached (negative if it is reached too
+    /// late), or `None` if it is not reached within the horizon of the CDF.
+    pub slack: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ProbabilitySlack {
+    pub time: f32,
+    /// How much more probability than required is reached by this time (negative if too little).
+    pub slack: f32,
+}
+
+/// The slack at one point of a [`Requirement`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PointSlack {
+    pub time: f32,
+    pub probability: f32,
+    pub time_slack: Option<f32>,
+    pub probability_slack: f32,
+}
+
+impl Requirement {
+    /// Create a requirement from `(time, probability)` points.
+    ///
+    /// The same restrictions as for [`CDF::step`] apply: times must be non-negative and
+    /// probabilities from (0, 1], and both must be strictly increasing.
+    pub fn new(points: &[(f32, f32)]) -> Result<Self, CDFError> {
+        if !points
+            .iter()
+            .all(|&(x, y)| x >= 0.0 && x.is_finite() && y > 0.0 && y <= 1.0)
+        {
+            return Err(CDFError::InvalidDataRange);
+        }
+        if !points
+            .windows(2)
+            .all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1)
+        {
+            return Err(CDFError::NonMonotonicData);
+        }
+        Ok(Self {
+            points: points.to_vec(),
+        })
+    }
+
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
+
+    /// Get the step function CDF representing this requirement on a grid of the given bin size.
+    ///
+    /// A CDF of the same bin size satisfies the requirement if and only if it is greater than or
+    /// equal to this CDF.
+    pub fn to_cdf(&self, bin_size: f32) -> Result<CDF, CDFError> {
+        if !(bin_size > 0.0 && bin_size.is_finite()) {
+            return Err(CDFError::InvalidBinSize);
+        }
+        let last = self.points.last().map_or(0.0, |p| p.0);
+        CDF::step(
+            &self.points,
+            bin_size,
+            (last / bin_size).floor() as usize + 1,
+        )
+    }
+
+    /// Check the given CDF against this requirement.
+    pub fn check(&self, cdf: &CDF) -> Verdict {
+        // tolerance for rounding errors when comparing times on the CDF's grid
+        let epsilon = cdf.bin_size() * 1e-4;
+        let violations: Vec<_> = self
+            .points
+            .iter()
+            .filter_map(|&(time, required)| {
+                let reached = cdf.quantile(required);
+                if reached.is_some_and(|t| t <= time + epsilon) {
+                    return None;
+                }
+                Some(Violation {
+                    time,
+                    required,
+                    actual: cdf.probability_at(time),
+                    late_by: reached.map(|t| t - time),
+                })
+            })
+            .collect();
+        if violations.is_empty() {
+            Verdict::Satisfied
+        } else {
+            Verdict::Violated(violations)
+        }
+    }
+
+    /// Get the time by which the given probability must be reached, or `None` if it is not
+    /// required.
+    fn required_time(&self, probability: f32) -> Option<f32> {
+        self.points.iter().find(|p| p.1 >= probability).map(|p| p.0)
+    }
+
+    /// Get the probability that must be reached by the given time.
+    fn required_probability(&self, time: f32) -> f32 {
+        self.points
+            .iter()
+            .take_while(|p| p.0 <= time)
+            .last()
+            .map_or(0.0, |p| p.1)
+    }
+
+    /// Compute the headroom of the given CDF with respect to this requirement.
+    ///
+    /// Since the CDF is non-decreasing and the requirement is a step function, the slack in
+    /// either direction is smallest at the points of the requirement; the tightest point is the
+    /// one with the least time slack, where not reaching the probability at all is worst.
+    pub fn slack(&self, cdf: &CDF) -> Slack {
+        // tolerance for rounding errors when comparing times on the CDF's grid
+        let epsilon = cdf.bin_size() * 1e-4;
+        // tolerance for CDF values that are meant to equal a required probability
+        const QUANTUM: f32 = 1.0 / 65535.0;
+
+        let max_probability = self.points.last().map_or(0.0, |p| p.1);
+        let mut levels: Vec<f32> = cdf
+            .iter()
+            .map(|(_, p)| p)
+            .filter(|&p| p > 0.0 && p <= max_probability)
+            .filter(|&p| self.points.iter().all(|q| (q.1 - p).abs() > QUANTUM))
+            .chain(self.points.iter().map(|p| p.1))
+            .collect();
+        levels.sort_by(f32::total_cmp);
+        levels.dedup();
+        let time = levels
+            .into_iter()
+            .map(|probability| TimeSlack {
+                probability,
+                slack: self
+                    .required_time(probability)
+                    .zip(cdf.quantile(probability))
+                    .map(|(required, reached)| required - reached),
+            })
+            .collect();
+
+        let first = self.points.first().map_or(f32::INFINITY, |p| p.0);
+        let bins = (cdf.width() / cdf.bin_size()).round() as usize;
+        let mut times: Vec<f32> = (0..bins)
+            .map(|i| i as f32 * cdf.bin_size())
+            .filter(|&t| t >= first - epsilon)
+            .chain(self.points.iter().map(|p| p.0))
+            .collect();
+        times.sort_by(f32::total_cmp);
+        times.dedup_by(|a, b| (*a - *b).abs() <= epsilon);
+        let probability = times
+            .into_iter()
+            .map(|time| ProbabilitySlack {
+                time,
+                slack: cdf.probability_at(time) - self.required_probability(time + epsilon),
+            })
+            .collect();
+
+        let tightest = self
+            .points
+            .iter()
+            .map(|&(time, probability)| PointSlack {
+                time,
+                probability,
+                time_slack: cdf.quantile(probability).map(|t| time - t),
+                probability_slack: cdf.probability_at(time) - probability,
+            })
+            .min_by(|a, b| {
+                let key = |p: &PointSlack| {
+                    (
+                        p.time_slack.unwrap_or(f32::NEG_INFINITY),
+                        p.probability_slack,
+                    )
+                };
+                key(a)
+                    .partial_cmp(&key(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+
+        Slack {
+            time,
+            probability,
+            tightest,
+        }
+    }
+}
+
+impl TryFrom<Vec<(f32, f32)>> for Requirement {
+    type Error = CDFError;
+
+    fn try_from(points: Vec<(f32, f32)>) -> Result<Self, Self::Error> {
+        Self::new(&points)
+    }
+}
+
+impl From<Requirement> for Vec<(f32, f32)> {
+    fn from(value: Requirement) -> Self {
+        value.points
+    }
+}
+
+impl Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (time, probability)) in self.points.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}% by {}", probability * 100.0, time)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}% by {}: only {:.4}%",
+            self.required * 100.0,
+            self.time,
+            self.actual * 100.0
+        )?;
+        match self.late_by {
+            Some(late_by) => write!(f, ", reached {:.4} later", late_by),
+            None => write!(f, ", not reached within the horizon"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requirement() {
+        let req = Requirement::new(&[(0.1, 0.5), (0.5, 0.95), (2.0, 0.999)]).unwrap();
+        assert_eq!(req.to_string(), "50% by 0.1, 95% by 0.5, 99.9% by 2");
+
+        let good = CDF::step(&[(0.05, 0.6), (0.5, 0.999)], 0.01, 300).unwrap();
+        assert_eq!(req.check(&good), Verdict::Satisfied);
+        assert!(good >= req.to_cdf(0.01).unwrap());
+
+        let bad = CDF::step(&[(0.05, 0.6), (0.8, 0.98)], 0.01, 300).unwrap();
+        let Verdict::Violated(violations) = req.check(&bad) else {
+            panic!("expected violation");
+        };
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].time, 0.5);
+        assert_eq!(format!("{:.4}", violations[0].actual), "0.6000");
+        assert_eq!(format!("{:.4}", violations[0].late_by.unwrap()), "0.3000");
+        assert_eq!(violations[1].late_by, None);
+        assert_eq!(
+            violations[1].to_string(),
+            "99.9% by 2: only 98.0011%, not reached within the horizon"
+        );
+        assert!(bad.partial_cmp(&req.to_cdf(0.01).unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_requirement_boundaries() {
+        let req = Requirement::new(&[(0.3, 1.0)]).unwrap();
+        let exact = CDF::step(&[(0.3, 1.0)], 0.1, 5).unwrap();
+        assert_eq!(req.check(&exact), Verdict::Satisfied);
+        let late = CDF::step(&[(0.4, 1.0)], 0.1, 5).unwrap();
+        assert!(matches!(req.check(&late), Verdict::Violated(_)));
+        assert!(late < req.to_cdf(0.1).unwrap());
+        for bin_size in [0.0, -0.1, f32::NAN, f32::INFINITY] {
+            assert_eq!(req.to_cdf(bin_size), Err(CDFError::InvalidBinSize));
+        }
+
+        // a level reached exactly by a floating point CDF satisfies the requirement
+        let samples: Vec<f32> = (0..100).map(|i| i as f32 * 0.001).collect();
+        let empirical = CDF::from_samples(&samples, 0, Some(0.001)).unwrap();
+        let met = Requirement::new(&[(0.098, 0.99)]).unwrap();
+        assert_eq!(met.check(&empirical), Verdict::Satisfied);
+
+        assert_eq!(
+            Requirement::new(&[(0.3, 0.5), (0.2, 0.6)]),
+            Err(CDFError::NonMonotonicData)
+        );
+        assert_eq!(
+            Requirement::new(&[(0.3, 0.0)]),
+            Err(CDFError::InvalidDataRange)
+        );
+
+        let json = serde_json::to_string(&req).unwrap();
+        assert_eq!(json, "[[0.3,1.0]]");
+        assert_eq!(serde_json::from_str::<Requirement>(&json).unwrap(), req);
+        assert!(serde_json::from_str::<Requirement>("[[0.3,0.5],[0.2,0.6]]").is_err());
+    }
+
+    #[test]
+    fn test_slack() {
+        let req = Requirement::new(&[(0.2, 0.5), (0.4, 0.9)]).unwrap();
+        let cdf = CDF::step(&[(0.1, 0.5), (0.3, 0.8), (0.5, 1.0)], 0.1, 6).unwrap();
+        let slack = req.slack(&cdf);
+        let time = slack
+            .time
+            .iter()
+            .map(|s| format!("{:.2}: {:.4}", s.probability, s.slack.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(time, ["0.50: 0.1000", "0.80: 0.1000", "0.90: -0.1000"]);
+        let probability = slack
+            .probability
+            .iter()
+            .map(|s| format!("{:.1}: {:.4}", s.time, s.slack))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            probability,
+            ["0.2: 0.0000", "0.3: 0.3000", "0.4: -0.1000", "0.5: 0.1000"]
+        );
+        let tightest = slack.tightest.unwrap();
+        assert_eq!((tightest.time, tightest.probability), (0.4, 0.9));
+        assert_eq!(format!("{:.4}", tightest.time_slack.unwrap()), "-0.1000");
+        assert_eq!(format!("{:.4}", tightest.probability_slack), "-0.1000");
+
+        // not reaching a probability at all is tighter than any delay
+        let short = CDF::step(&[(0.1, 0.5), (0.3, 0.8)], 0.1, 6).unwrap();
+        let slack = req.slack(&short);
+        assert_eq!(slack.time.last().unwrap().slack, None);
+        assert_eq!(slack.tightest.unwrap().time_slack, None);
+    }
+}